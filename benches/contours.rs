@@ -0,0 +1,126 @@
+//! Time `calculate_collisions` for every chunk of the bundled terrain images,
+//! and meshing the whole world at startup on one thread and in parallel.
+//!
+//! Run with `cargo bench --bench contours`, and on an older commit to compare.
+
+use std::time::{Duration, Instant};
+
+use dig_n_ripoff::{
+    mst::{
+        chunk::Chunk,
+        marching_square::{
+            calculate_collisions, calculate_terrain_collisions, calculate_terrain_collisions_par,
+            CollisionOptions, MarchingMode,
+        },
+        world_gen::{gen_from_image, DATA_PATH},
+    },
+    resources::{Materials, Terrain},
+    util::Vector2I,
+};
+
+const IMAGES: [&str; 7] = [
+    "chunk_0.png",
+    "chunk_1.png",
+    "complex_terrain.png",
+    "noise.png",
+    "simple_terrain.png",
+    "solid_4x4.png",
+    "terrain.png",
+];
+const ITERATIONS: u32 = 20;
+
+/// Average time of meshing every chunk once, and the slowest single chunk
+fn measure(
+    chunks: &[&Chunk],
+    materials: &Materials,
+    options: &CollisionOptions,
+) -> (Duration, Duration) {
+    let (mut total, mut slowest) = (Duration::ZERO, Duration::ZERO);
+    for _ in 0..ITERATIONS {
+        for chunk in chunks {
+            let now = Instant::now();
+            let outlines = calculate_collisions(chunk, materials, options);
+            let elapsed = now.elapsed();
+            std::hint::black_box(outlines);
+            total += elapsed;
+            slowest = slowest.max(elapsed);
+        }
+    }
+    (total / ITERATIONS, slowest)
+}
+
+fn main() {
+    let materials = Materials::default();
+    let modes = [
+        ("blocky", MarchingMode::Blocky),
+        ("interpolated", MarchingMode::Interpolated),
+    ];
+
+    // Every texel has its own outline, the worst case for connecting sides
+    let mut checkerboard = Chunk::new();
+    for y in 0..Chunk::SIZE.y {
+        for x in 0..Chunk::SIZE.x {
+            if (x + y) % 2 == 0 {
+                checkerboard.set_texel(&Vector2I { x, y }, 1);
+            }
+        }
+    }
+
+    println!(
+        "{:<22} {:<13} {:>7} {:>12} {:>12}",
+        "terrain", "mode", "chunks", "total", "slowest"
+    );
+    for image in IMAGES {
+        let chunk_map = gen_from_image(&format!("./assets/terrain/{image}"), &materials);
+        let chunks: Vec<&Chunk> = chunk_map.values().collect();
+        for (name, mode) in modes {
+            let options = CollisionOptions {
+                mode,
+                ..CollisionOptions::default()
+            };
+            let (total, slowest) = measure(&chunks, &materials, &options);
+            println!(
+                "{image:<22} {name:<13} {:>7} {total:>12.2?} {slowest:>12.2?}",
+                chunks.len()
+            );
+        }
+    }
+    for (name, mode) in modes {
+        let options = CollisionOptions {
+            mode,
+            ..CollisionOptions::default()
+        };
+        let (total, slowest) = measure(&[&checkerboard], &materials, &options);
+        println!(
+            "{:<22} {name:<13} {:>7} {total:>12.2?} {slowest:>12.2?}",
+            "checkerboard", 1
+        );
+    }
+
+    // Startup meshes every chunk of the world at once
+    let terrain = Terrain::from_chunks(gen_from_image(DATA_PATH, &materials));
+    let indices: Vec<Vector2I> = terrain.chunk_iter().map(|(index, _)| *index).collect();
+    let options = CollisionOptions::default();
+    let (mut serial, mut parallel) = (Duration::ZERO, Duration::ZERO);
+    for _ in 0..ITERATIONS {
+        let now = Instant::now();
+        for index in indices.iter() {
+            std::hint::black_box(calculate_terrain_collisions(
+                &terrain, index, &materials, &options,
+            ));
+        }
+        serial += now.elapsed();
+
+        let now = Instant::now();
+        std::hint::black_box(calculate_terrain_collisions_par(
+            &terrain, &indices, &materials, &options,
+        ));
+        parallel += now.elapsed();
+    }
+    println!(
+        "\nstartup {DATA_PATH} ({} chunks): serial {:.2?}, parallel {:.2?}",
+        indices.len(),
+        serial / ITERATIONS,
+        parallel / ITERATIONS
+    );
+}
@@ -11,6 +11,8 @@ pub struct RenderTarget<'a> {
     pub pivot: Vector2F,
     pub sorting_order: i16,
     pub use_screen_space: bool,
+    /// The surface has changed since it was last uploaded to a texture
+    dirty: bool,
 }
 
 impl<'a> RenderTarget<'a> {
@@ -26,6 +28,7 @@ impl<'a> RenderTarget<'a> {
             pivot,
             sorting_order,
             use_screen_space,
+            dirty: true,
         }
     }
 
@@ -51,6 +54,20 @@ impl<'a> RenderTarget<'a> {
 
     pub fn set_surface(&mut self, surface: Surface<'a>) {
         self.surface = UnsafeSurface::new(surface);
+        self.dirty = true;
+    }
+
+    /// Upload the surface again before it's drawn next, call this after changing the surface's pixels
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
     }
 }
 
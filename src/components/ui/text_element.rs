@@ -12,6 +12,8 @@ pub struct TextElement {
     font: Arc<Mutex<UnsafeSendSync<Font<'static, 'static>>>>,
     text: String,
     color: Color,
+    /// Changed since it was last rendered
+    dirty: bool,
 }
 
 impl TextElement {
@@ -27,6 +29,7 @@ impl TextElement {
             font: (*DEFAULT_FONT).clone(),
             text: String::new(),
             color: Self::DEFAULT_COLOR,
+            dirty: true,
         }
     }
 
@@ -42,6 +45,7 @@ impl TextElement {
 
     pub fn set_font(&mut self, font: Arc<Mutex<UnsafeSendSync<Font<'static, 'static>>>>) {
         self.font = font;
+        self.dirty = true;
     }
 
     pub fn get_text(&self) -> &str {
@@ -49,7 +53,10 @@ impl TextElement {
     }
 
     pub fn set_text(&mut self, text: &str) {
-        self.text = text.to_string();
+        if self.text != text {
+            self.text = text.to_string();
+            self.dirty = true;
+        }
     }
 
     pub fn get_color(&self) -> Color {
@@ -57,6 +64,17 @@ impl TextElement {
     }
 
     pub fn set_color(&mut self, color: Color) {
-        self.color = color;
+        if self.color != color {
+            self.color = color;
+            self.dirty = true;
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
     }
 }
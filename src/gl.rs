@@ -1 +1,2 @@
 pub mod renderer;
+pub mod texture_cache;
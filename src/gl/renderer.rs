@@ -1,10 +1,14 @@
+use std::path::Path;
+
 use sdl2::{
+    image::SaveSurface,
     pixels::{Color, PixelFormatEnum},
     rect::{Point, Rect},
-    render::Canvas,
+    render::{Canvas, Texture},
     surface::Surface,
+    ttf::Font,
     video::Window,
-    EventPump, Sdl, ttf::Font,
+    EventPump, Sdl,
 };
 
 pub const INIT_WINDOW_SIZE: (u32, u32) = (1024, 1024);
@@ -15,32 +19,56 @@ use unsafe_send_sync::UnsafeSendSync;
 
 use crate::util::Vector2I;
 
+/// Where the frames are drawn
+pub enum RenderCanvas {
+    Window(Canvas<Window>),
+    /// Software renderer drawing into an offscreen surface, works without a display or GPU
+    Headless(Canvas<Surface<'static>>),
+}
+
 pub type UnsafeSurface<'a> = UnsafeSendSync<Surface<'a>>;
-pub type UnsafeCanvas = UnsafeSendSync<Canvas<Window>>;
+pub type UnsafeCanvas = UnsafeSendSync<RenderCanvas>;
 pub type UnsafeFont = UnsafeSendSync<Font<'static, 'static>>;
 
-pub fn init() -> (Sdl, UnsafeCanvas, EventPump) {
+/// Run the same code with either kind of canvas
+macro_rules! with_canvas {
+    ($render_canvas:expr, $canvas:ident => $body:expr) => {
+        match $render_canvas {
+            RenderCanvas::Window($canvas) => $body,
+            RenderCanvas::Headless($canvas) => $body,
+        }
+    };
+}
+
+/// Init SDL and the canvas. A headless canvas has the size of the window, but doesn't open one.
+pub fn init(headless: bool) -> (Sdl, UnsafeCanvas, EventPump) {
     let sdl_context = match sdl2::init() {
         Ok(context) => context,
         Err(error) => panic!("Failed to init sdl context: {error:?}"),
     };
-    let video_subsystem = match sdl_context.video() {
-        Ok(video) => video,
-        Err(error) => panic!("Failed to get sdl video subsystem: {error:?}"),
-    };
 
-    let window = match video_subsystem
-        .window("rust-sdl2 demo", INIT_WINDOW_SIZE.0, INIT_WINDOW_SIZE.1)
-        .position_centered()
-        .build()
-    {
-        Ok(window) => window,
-        Err(error) => panic!("Failed to create window: {error:?}"),
-    };
+    let canvas = match headless {
+        true => init_headless(INIT_WINDOW_SIZE.0, INIT_WINDOW_SIZE.1),
+        false => {
+            let video_subsystem = match sdl_context.video() {
+                Ok(video) => video,
+                Err(error) => panic!("Failed to get sdl video subsystem: {error:?}"),
+            };
+
+            let window = match video_subsystem
+                .window("rust-sdl2 demo", INIT_WINDOW_SIZE.0, INIT_WINDOW_SIZE.1)
+                .position_centered()
+                .build()
+            {
+                Ok(window) => window,
+                Err(error) => panic!("Failed to create window: {error:?}"),
+            };
 
-    let canvas = match window.into_canvas().build() {
-        Ok(canvas) => UnsafeCanvas::new(canvas),
-        Err(error) => panic!("Failed to create window canvas: {error:?}"),
+            match window.into_canvas().build() {
+                Ok(canvas) => UnsafeCanvas::new(RenderCanvas::Window(canvas)),
+                Err(error) => panic!("Failed to create window canvas: {error:?}"),
+            }
+        }
     };
 
     let event_pump = match sdl_context.event_pump() {
@@ -51,30 +79,49 @@ pub fn init() -> (Sdl, UnsafeCanvas, EventPump) {
     (sdl_context, canvas, event_pump)
 }
 
+/// Offscreen canvas using SDL's software renderer, doesn't need `init`
+pub fn init_headless(width: u32, height: u32) -> UnsafeCanvas {
+    let surface = match Surface::new(width, height, SURFACE_FORMAT) {
+        Ok(surface) => surface,
+        Err(error) => panic!("Failed to create headless surface: {error:?}"),
+    };
+    match surface.into_canvas() {
+        Ok(canvas) => UnsafeCanvas::new(RenderCanvas::Headless(canvas)),
+        Err(error) => panic!("Failed to create headless canvas: {error:?}"),
+    }
+}
+
 pub fn begin_draw(canvas: &mut UnsafeCanvas) {
-    canvas.set_draw_color(Color::RGB(30, 30, 30));
-    canvas.clear();
+    with_canvas!(&mut **canvas, canvas => {
+        canvas.set_draw_color(Color::RGB(30, 30, 30));
+        canvas.clear();
+    })
 }
 
 pub fn finish_draw(canvas: &mut UnsafeCanvas) {
-    canvas.present();
+    with_canvas!(&mut **canvas, canvas => canvas.present())
 }
 
-pub fn draw_surface(canvas: &mut UnsafeCanvas, surface: &UnsafeSurface, src: Rect, dst: Rect) {
-    let texture_creator = canvas.texture_creator();
-    let texture = match surface.as_texture(&texture_creator) {
+pub fn create_texture(canvas: &UnsafeCanvas, surface: &Surface) -> Texture {
+    let texture = with_canvas!(&**canvas, canvas => {
+        canvas.texture_creator().create_texture_from_surface(surface)
+    });
+    match texture {
         Ok(texture) => texture,
         Err(error) => panic!("Failed to create texture from surface: {error:?}"),
-    };
-    match canvas.copy(&texture, src, dst) {
+    }
+}
+
+pub fn draw_texture(canvas: &mut UnsafeCanvas, texture: &Texture, src: Rect, dst: Rect) {
+    match with_canvas!(&mut **canvas, canvas => canvas.copy(texture, src, dst)) {
         Ok(_) => {}
-        Err(error) => panic!("Failed to draw surface to canvas: {error:?}"),
+        Err(error) => panic!("Failed to draw texture to canvas: {error:?}"),
     };
 }
 
-pub fn draw_surface_rotated(
+pub fn draw_texture_rotated(
     canvas: &mut UnsafeCanvas,
-    surface: &UnsafeSurface,
+    texture: &mut Texture,
     modulation: Color,
     src: Rect,
     dst: Rect,
@@ -83,26 +130,13 @@ pub fn draw_surface_rotated(
     flip_h: bool,
     flip_v: bool,
 ) {
-    let texture_creator = canvas.texture_creator();
-    let texture = match surface.as_texture(&texture_creator) {
-        Ok(mut texture) => {
-            texture.set_color_mod(modulation.r, modulation.g, modulation.b);
-            texture.set_alpha_mod(modulation.a);
-            texture
-        }
-        Err(error) => panic!("Failed to create texture from surface: {error:?}"),
-    };
-    match canvas.copy_ex(
-        &texture,
-        src,
-        dst,
-        angle.to_degrees(),
-        pivot,
-        flip_h,
-        flip_v,
-    ) {
+    texture.set_color_mod(modulation.r, modulation.g, modulation.b);
+    texture.set_alpha_mod(modulation.a);
+    match with_canvas!(&mut **canvas, canvas => {
+        canvas.copy_ex(texture, src, dst, angle.to_degrees(), pivot, flip_h, flip_v)
+    }) {
         Ok(_) => {}
-        Err(error) => panic!("Failed to draw surface to canvas: {error:?}"),
+        Err(error) => panic!("Failed to draw texture to canvas: {error:?}"),
     };
 }
 
@@ -115,7 +149,6 @@ pub fn draw_line_loop(
         Some(color) => Color::RGBA(color.0, color.1, color.2, color.3),
         None => Color::RGBA(0, 255, 255, 128),
     };
-    canvas.set_draw_color(color);
     let mut p: Vec<Point> = Vec::new();
     for point in points {
         p.push(Point::new(point.x, point.y));
@@ -123,8 +156,82 @@ pub fn draw_line_loop(
     if p.first().is_some() && p.last().is_some() && p.first() != p.last() {
         p.push(*p.first().unwrap());
     }
-    match canvas.draw_lines(&p[..]) {
+    match with_canvas!(&mut **canvas, canvas => {
+        canvas.set_draw_color(color);
+        canvas.draw_lines(&p[..])
+    }) {
         Ok(_) => {}
         Err(error) => panic!("Failed to draw points: {error:?}"),
     };
 }
+
+/// Copy of the finished frame
+pub fn read_frame(canvas: &UnsafeCanvas) -> Result<Surface<'static>, String> {
+    let ((width, height), pixels) = with_canvas!(&**canvas, canvas => {
+        (canvas.output_size()?, canvas.read_pixels(None, SURFACE_FORMAT)?)
+    });
+    let mut surface = Surface::new(width, height, SURFACE_FORMAT)?;
+    let pitch = surface.pitch() as usize;
+    let row_length = width as usize * SURFACE_FORMAT_BPP;
+    surface.with_lock_mut(|p_data| {
+        for (y, row) in pixels.chunks_exact(row_length).enumerate() {
+            p_data[y * pitch..y * pitch + row_length].copy_from_slice(row);
+        }
+    });
+    Ok(surface)
+}
+
+/// Save the finished frame as a PNG image
+pub fn save_png(canvas: &UnsafeCanvas, path: &Path) -> Result<(), String> {
+    read_frame(canvas)?.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use sdl2::rect::Rect;
+
+    use super::{
+        begin_draw, create_texture, draw_line_loop, draw_texture, init_headless, read_frame,
+        save_png, UnsafeCanvas, SURFACE_FORMAT_BPP,
+    };
+    use crate::{
+        components::RenderTarget,
+        util::{TempPath, Vector2F, Vector2I},
+    };
+
+    fn pixel(canvas: &UnsafeCanvas, x: usize, y: usize) -> (u8, u8, u8) {
+        let frame = read_frame(canvas).unwrap();
+        let i = y * frame.pitch() as usize + x * SURFACE_FORMAT_BPP;
+        frame.with_lock(|p_data| (p_data[i], p_data[i + 1], p_data[i + 2]))
+    }
+
+    #[test]
+    fn headless_frame() {
+        let mut canvas = init_headless(16, 16);
+        begin_draw(&mut canvas);
+        assert_eq!(pixel(&canvas, 8, 8), (30, 30, 30));
+
+        let render_target =
+            RenderTarget::new_filled(4, 4, Vector2F::ZERO, 0, false, (255, 0, 0, 255));
+        let texture = create_texture(&canvas, &render_target.surface);
+        draw_texture(
+            &mut canvas,
+            &texture,
+            Rect::new(0, 0, 4, 4),
+            Rect::new(2, 2, 4, 4),
+        );
+        draw_line_loop(
+            &mut canvas,
+            vec![Vector2I { x: 10, y: 10 }, Vector2I { x: 14, y: 10 }],
+            Some((0, 0, 255, 255)),
+        );
+        assert_eq!(pixel(&canvas, 3, 3), (255, 0, 0));
+        assert_eq!(pixel(&canvas, 12, 10), (0, 0, 255));
+        assert_eq!(pixel(&canvas, 8, 8), (30, 30, 30));
+
+        let path = TempPath::new("dig_n_ripoff_headless_frame.png");
+        save_png(&canvas, path.as_ref()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[1..4], b"PNG");
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use sdl2::render::Texture;
+use specs::Entity;
+use unsafe_send_sync::UnsafeSendSync;
+
+use super::renderer::{self, UnsafeCanvas};
+use crate::components::RenderTarget;
+
+pub type UnsafeTextureCache = UnsafeSendSync<TextureCache>;
+
+/// Textures of render targets, kept between frames.
+///
+/// A render target's surface is uploaded again only after the render target has been marked dirty,
+/// or after its entry has been invalidated. The cache has to be dropped or cleared before the canvas.
+pub struct TextureCache {
+    textures: HashMap<Entity, Texture>,
+}
+
+impl TextureCache {
+    pub fn new() -> TextureCache {
+        TextureCache {
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Texture of the entity's render target, uploaded from the surface if needed
+    pub fn get(
+        &mut self,
+        canvas: &UnsafeCanvas,
+        entity: Entity,
+        render_target: &mut RenderTarget,
+    ) -> &mut Texture {
+        if render_target.is_dirty() {
+            self.invalidate(entity);
+            render_target.clear_dirty();
+        }
+        self.textures
+            .entry(entity)
+            .or_insert_with(|| renderer::create_texture(canvas, &render_target.surface))
+    }
+
+    /// Free the entity's texture, it's uploaded again the next time it's drawn
+    pub fn invalidate(&mut self, entity: Entity) {
+        match self.textures.remove(&entity) {
+            // Safety: the cache is only used with a renderer that outlives its textures
+            Some(texture) => unsafe { texture.destroy() },
+            None => (),
+        }
+    }
+
+    /// Free the textures of entities that `keep` rejects
+    pub fn retain(&mut self, keep: impl Fn(Entity) -> bool) {
+        let removed: Vec<Entity> = self
+            .textures
+            .keys()
+            .filter(|entity| !keep(**entity))
+            .copied()
+            .collect();
+        for entity in removed {
+            self.invalidate(entity);
+        }
+    }
+
+    /// Free all textures, for example when the renderer is replaced
+    pub fn clear(&mut self) {
+        self.retain(|_| false);
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+}
+
+impl Default for TextureCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TextureCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdl2::{pixels::Color, rect::Rect, render::Texture};
+    use specs::{Builder, World, WorldExt};
+
+    use super::TextureCache;
+    use crate::{
+        components::RenderTarget,
+        gl::renderer::{draw_texture, init_headless, read_frame, UnsafeCanvas},
+        util::Vector2F,
+    };
+
+    fn draw(canvas: &mut UnsafeCanvas, texture: &Texture) -> (u8, u8, u8) {
+        let rect = Rect::new(0, 0, 4, 4);
+        draw_texture(canvas, texture, rect, rect);
+        read_frame(canvas)
+            .unwrap()
+            .with_lock(|p_data| (p_data[0], p_data[1], p_data[2]))
+    }
+
+    fn fill(render_target: &mut RenderTarget, (r, g, b): (u8, u8, u8)) {
+        let rect = render_target.surface.rect();
+        render_target
+            .surface
+            .fill_rect(rect, Color::RGB(r, g, b))
+            .unwrap();
+    }
+
+    #[test]
+    fn upload_when_dirty() {
+        let mut world = World::new();
+        let entity = world.create_entity().build();
+        let mut canvas = init_headless(4, 4);
+        let mut cache = TextureCache::new();
+        let mut render_target =
+            RenderTarget::new_filled(4, 4, Vector2F::ZERO, 0, false, (255, 0, 0, 255));
+
+        let texture = cache.get(&canvas, entity, &mut render_target);
+        assert_eq!(draw(&mut canvas, texture), (255, 0, 0));
+        assert_eq!(cache.len(), 1);
+
+        // Changes to the surface show up only after marking it dirty
+        fill(&mut render_target, (0, 255, 0));
+        let texture = cache.get(&canvas, entity, &mut render_target);
+        assert_eq!(draw(&mut canvas, texture), (255, 0, 0));
+        render_target.mark_dirty();
+        let texture = cache.get(&canvas, entity, &mut render_target);
+        assert_eq!(draw(&mut canvas, texture), (0, 255, 0));
+
+        // Or after invalidating the texture
+        fill(&mut render_target, (0, 0, 255));
+        cache.invalidate(entity);
+        let texture = cache.get(&canvas, entity, &mut render_target);
+        assert_eq!(draw(&mut canvas, texture), (0, 0, 255));
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
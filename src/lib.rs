@@ -0,0 +1,13 @@
+//! Destructible pixel terrain on top of specs and Box2D.
+//!
+//! The binary only sets up the window and feeds input into a [`simulation::Simulation`],
+//! everything else can be used by tools, benchmarks and integration tests.
+
+pub mod components;
+pub mod gl;
+pub mod mst;
+pub mod replay;
+pub mod resources;
+pub mod simulation;
+pub mod systems;
+pub mod util;
@@ -1,5 +1,10 @@
+pub mod brush;
 pub mod chunk;
+pub mod connectivity;
+pub mod falling_sand;
 pub mod marching_square;
+pub mod persistence;
+pub mod simplify;
 pub mod texel;
 pub mod utils;
 pub mod world_gen;
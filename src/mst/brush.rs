@@ -0,0 +1,451 @@
+//! Terrain editing tools used by `TerrainPainter`.
+//!
+//! Everything works on global texel positions of a `Terrain`, random brushes take a seed
+//! so that the same stroke always gives the same result.
+
+use std::collections::{HashSet, VecDeque};
+
+use super::texel::TexelID;
+use crate::{
+    resources::{Materials, Terrain},
+    util::{noise, Vector2I},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushShape {
+    Circle,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    /// Stamp the shape along the cursor
+    Brush(BrushShape),
+    /// Stamp circles along a straight line from where the stroke started to where it ended
+    Line,
+    /// Replace the connected area of the same material
+    Fill,
+    /// Set random texels inside a circle
+    Spray,
+    /// Remove diggable materials, with a soft edge
+    Dig,
+    /// Select a rectangle to copy
+    Select,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 7] = [
+        Tool::Brush(BrushShape::Circle),
+        Tool::Brush(BrushShape::Square),
+        Tool::Line,
+        Tool::Fill,
+        Tool::Spray,
+        Tool::Dig,
+        Tool::Select,
+    ];
+}
+
+/// Largest area `flood_fill` changes at once
+pub const MAX_FILL_SIZE: usize = 64 * 64;
+
+/// Share of texels `spray` sets
+pub const SPRAY_DENSITY: f32 = 0.1;
+
+/// Set a texel, skipping texels that already have the id
+fn set_texel(terrain: &mut Terrain, position: Vector2I, id: TexelID) {
+    match terrain.global_to_texel(&position) {
+        Some(texel) if texel.id == id => (),
+        _ => terrain.set_texel(&position, id),
+    }
+}
+
+/// Random value in range [0, 1) for a position
+fn random(seed: u64, position: Vector2I) -> f32 {
+    (noise::hash(seed, position.x, position.y) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+pub fn paint_circle(terrain: &mut Terrain, origin: Vector2I, radius: i32, id: TexelID) {
+    for y in origin.y - (radius - 1)..origin.y + radius {
+        for x in origin.x - (radius - 1)..origin.x + radius {
+            let dx = (x - origin.x).abs();
+            let dy = (y - origin.y).abs();
+            if dx * dx + dy * dy <= (radius - 1) * (radius - 1) {
+                set_texel(terrain, Vector2I { x, y }, id)
+            }
+        }
+    }
+}
+
+pub fn paint_square(terrain: &mut Terrain, origin: Vector2I, radius: i32, id: TexelID) {
+    for y in origin.y - (radius - 1)..origin.y + radius {
+        for x in origin.x - (radius - 1)..origin.x + radius {
+            set_texel(terrain, Vector2I { x, y }, id)
+        }
+    }
+}
+
+pub fn paint_shape(
+    terrain: &mut Terrain,
+    shape: BrushShape,
+    origin: Vector2I,
+    radius: i32,
+    id: TexelID,
+) {
+    match shape {
+        BrushShape::Circle => paint_circle(terrain, origin, radius, id),
+        BrushShape::Square => paint_square(terrain, origin, radius, id),
+    }
+}
+
+/// Positions from `from` to `to` with Bresenham's algorithm, both included
+pub fn line_points(from: Vector2I, to: Vector2I) -> Vec<Vector2I> {
+    let dx = (to.x - from.x).abs();
+    let dy = -(to.y - from.y).abs();
+    let step = Vector2I {
+        x: (to.x - from.x).signum(),
+        y: (to.y - from.y).signum(),
+    };
+    let mut error = dx + dy;
+    let mut position = from;
+    let mut points: Vec<Vector2I> = vec![position];
+    while position != to {
+        // Both steps are decided by the error before either is taken
+        let double_error = 2 * error;
+        if double_error >= dy {
+            error += dy;
+            position.x += step.x;
+        }
+        if double_error <= dx {
+            error += dx;
+            position.y += step.y;
+        }
+        points.push(position);
+    }
+    points
+}
+
+/// Stamp the shape at every position of the line
+pub fn paint_line(
+    terrain: &mut Terrain,
+    shape: BrushShape,
+    from: Vector2I,
+    to: Vector2I,
+    radius: i32,
+    id: TexelID,
+) {
+    for position in line_points(from, to) {
+        paint_shape(terrain, shape, position, radius, id);
+    }
+}
+
+/// Replace the 4-connected area of texels with the same id as the origin.
+///
+/// Stays inside loaded chunks and stops after `MAX_FILL_SIZE` texels. Returns the number of changed texels.
+pub fn flood_fill(terrain: &mut Terrain, origin: Vector2I, id: TexelID) -> usize {
+    let target = match terrain.global_to_texel(&origin) {
+        Some(texel) if texel.id != id => texel.id,
+        _ => return 0,
+    };
+
+    let mut visited: HashSet<Vector2I> = HashSet::from([origin]);
+    let mut queue: VecDeque<Vector2I> = VecDeque::from([origin]);
+    let mut filled: Vec<Vector2I> = Vec::new();
+    while let Some(position) = queue.pop_front() {
+        if filled.len() >= MAX_FILL_SIZE {
+            break;
+        }
+        filled.push(position);
+        for offset in [
+            Vector2I::UP,
+            Vector2I::RIGHT,
+            Vector2I::DOWN,
+            Vector2I::LEFT,
+        ] {
+            let neighbour = position + offset;
+            match terrain.global_to_texel(&neighbour) {
+                Some(texel) if texel.id == target && visited.insert(neighbour) => {
+                    queue.push_back(neighbour)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    for position in filled.iter() {
+        terrain.set_texel(position, id);
+    }
+    filled.len()
+}
+
+/// Set about `SPRAY_DENSITY` of the texels in a circle
+pub fn spray(terrain: &mut Terrain, origin: Vector2I, radius: i32, id: TexelID, seed: u64) {
+    for y in origin.y - (radius - 1)..origin.y + radius {
+        for x in origin.x - (radius - 1)..origin.x + radius {
+            let position = Vector2I { x, y };
+            let dx = (x - origin.x).abs();
+            let dy = (y - origin.y).abs();
+            if dx * dx + dy * dy <= (radius - 1) * (radius - 1)
+                && random(seed, position) < SPRAY_DENSITY
+            {
+                set_texel(terrain, position, id)
+            }
+        }
+    }
+}
+
+/// Materials that can be dug out and their hardness, all except air and anchored materials
+pub fn diggable_materials(materials: &Materials) -> Vec<(TexelID, f32)> {
+    materials
+        .iter()
+        .filter(|(id, material)| *id != 0 && !material.anchored)
+        .map(|(id, material)| (id, material.hardness))
+        .collect()
+}
+
+/// Remove the diggable texels in a circle.
+///
+/// The inner half of the circle is removed, further out texels are removed with a chance that
+/// falls off towards the edge. The chance is divided by the hardness, so materials harder than 1
+/// only come off partially and softer ones lose more of the edge.
+pub fn dig(
+    terrain: &mut Terrain,
+    origin: Vector2I,
+    radius: i32,
+    diggable: &[(TexelID, f32)],
+    seed: u64,
+) {
+    let outer = (radius - 1).max(0) as f32;
+    let inner = outer * 0.5;
+    for y in origin.y - (radius - 1)..origin.y + radius {
+        for x in origin.x - (radius - 1)..origin.x + radius {
+            let position = Vector2I { x, y };
+            let distance = Vector2I {
+                x: x - origin.x,
+                y: y - origin.y,
+            };
+            let distance = ((distance.x * distance.x + distance.y * distance.y) as f32).sqrt();
+            if distance > outer {
+                continue;
+            }
+            let chance = match distance <= inner {
+                true => 1.0,
+                false => 1.0 - (distance - inner) / (outer - inner),
+            };
+            let hardness = match terrain.global_to_texel(&position) {
+                Some(texel) => match diggable.iter().find(|(id, _)| *id == texel.id) {
+                    Some((_, hardness)) => hardness.max(f32::EPSILON),
+                    None => continue,
+                },
+                None => continue,
+            };
+            if random(seed, position) < (chance / hardness).min(1.0) {
+                terrain.set_texel(&position, 0)
+            }
+        }
+    }
+}
+
+/// Copied rectangle of texels, `None` where the chunk wasn't loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub size: Vector2I,
+    pub texels: Vec<Option<TexelID>>,
+}
+
+impl Selection {
+    /// Copy the rectangle between two corners, both included
+    pub fn copy(terrain: &Terrain, a: Vector2I, b: Vector2I) -> Selection {
+        let min = Vector2I {
+            x: a.x.min(b.x),
+            y: a.y.min(b.y),
+        };
+        let max = Vector2I {
+            x: a.x.max(b.x),
+            y: a.y.max(b.y),
+        };
+        let mut texels: Vec<Option<TexelID>> = Vec::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                texels.push(
+                    terrain
+                        .global_to_texel(&Vector2I { x, y })
+                        .map(|texel| texel.id),
+                );
+            }
+        }
+        Selection {
+            size: max - min + Vector2I::ONE,
+            texels,
+        }
+    }
+
+    /// Paste with the top left corner at `origin`, skipping texels that weren't loaded
+    pub fn paste(&self, terrain: &mut Terrain, origin: Vector2I) {
+        for (i, id) in self.texels.iter().enumerate() {
+            let position = origin
+                + Vector2I {
+                    x: i as i32 % self.size.x,
+                    y: i as i32 / self.size.x,
+                };
+            match id {
+                Some(id) => set_texel(terrain, position, *id),
+                None => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{
+        dig, flood_fill, line_points, paint_circle, paint_line, paint_square, spray, BrushShape,
+        Selection, MAX_FILL_SIZE,
+    };
+    use crate::{
+        mst::{chunk::Chunk, texel::TexelID},
+        resources::Terrain,
+        util::Vector2I,
+    };
+
+    fn terrain() -> Terrain {
+        Terrain::from_chunks(HashMap::from([
+            (Vector2I::ZERO, Chunk::new()),
+            (Vector2I::RIGHT, Chunk::new()),
+        ]))
+    }
+
+    fn count(terrain: &Terrain, id: TexelID) -> usize {
+        terrain
+            .chunk_iter()
+            .flat_map(|(_, chunk)| chunk.texels.iter())
+            .filter(|texel| texel.id == id)
+            .count()
+    }
+
+    fn id_at(terrain: &Terrain, x: i32, y: i32) -> TexelID {
+        terrain.global_to_texel(&Vector2I { x, y }).unwrap().id
+    }
+
+    #[test]
+    fn shapes() {
+        let mut terrain = terrain();
+        paint_square(&mut terrain, Vector2I { x: 8, y: 8 }, 3, 1);
+        assert_eq!(count(&terrain, 1), 25);
+
+        paint_circle(&mut terrain, Vector2I { x: 40, y: 8 }, 3, 2);
+        assert_eq!(count(&terrain, 2), 13);
+        assert_eq!(id_at(&terrain, 42, 8), 2);
+        assert_eq!(id_at(&terrain, 42, 10), 0);
+    }
+
+    #[test]
+    fn lines() {
+        let points = line_points(Vector2I { x: 0, y: 0 }, Vector2I { x: 5, y: 2 });
+        assert_eq!(points.len(), 6);
+        assert_eq!(points.first(), Some(&Vector2I { x: 0, y: 0 }));
+        assert_eq!(points.last(), Some(&Vector2I { x: 5, y: 2 }));
+        assert_eq!(
+            line_points(Vector2I::ONE, Vector2I::ONE),
+            vec![Vector2I::ONE]
+        );
+        // Lines going up and to the left reach their end too
+        let points = line_points(Vector2I { x: 160, y: 80 }, Vector2I { x: 40, y: 20 });
+        assert_eq!(points.len(), 121);
+        assert_eq!(points.last(), Some(&Vector2I { x: 40, y: 20 }));
+
+        let mut terrain = terrain();
+        paint_line(
+            &mut terrain,
+            BrushShape::Square,
+            Vector2I { x: 4, y: 10 },
+            Vector2I { x: 50, y: 10 },
+            1,
+            1,
+        );
+        assert_eq!(count(&terrain, 1), 47);
+    }
+
+    #[test]
+    fn fill() {
+        let mut terrain = terrain();
+        // Wall off the left chunk from the right one
+        paint_line(
+            &mut terrain,
+            BrushShape::Square,
+            Vector2I { x: 31, y: 0 },
+            Vector2I { x: 31, y: 31 },
+            1,
+            1,
+        );
+        assert_eq!(
+            flood_fill(&mut terrain, Vector2I { x: 4, y: 4 }, 2),
+            31 * 32
+        );
+        assert_eq!(id_at(&terrain, 0, 31), 2);
+        assert_eq!(id_at(&terrain, 40, 4), 0);
+        // Filling with the same id does nothing
+        assert_eq!(flood_fill(&mut terrain, Vector2I { x: 4, y: 4 }, 2), 0);
+
+        let mut big = Terrain::from_chunks(
+            (0..4)
+                .flat_map(|y| (0..4).map(move |x| (Vector2I { x, y }, Chunk::new())))
+                .collect(),
+        );
+        assert_eq!(flood_fill(&mut big, Vector2I::ZERO, 1), MAX_FILL_SIZE);
+    }
+
+    #[test]
+    fn spray_and_dig() {
+        let mut a = terrain();
+        let mut b = terrain();
+        spray(&mut a, Vector2I { x: 16, y: 16 }, 12, 1, 7);
+        spray(&mut b, Vector2I { x: 16, y: 16 }, 12, 1, 7);
+        let sprayed = count(&a, 1);
+        assert!(sprayed > 0 && sprayed < 200, "{sprayed}");
+        assert_eq!(count(&b, 1), sprayed);
+
+        let mut terrain = terrain();
+        paint_square(&mut terrain, Vector2I { x: 16, y: 16 }, 16, 1);
+        paint_square(&mut terrain, Vector2I { x: 16, y: 16 }, 2, 5);
+        dig(&mut terrain, Vector2I { x: 16, y: 16 }, 9, &[(1, 1.0)], 3);
+        // Undiggable materials stay, the inner half is gone and the outside is untouched
+        assert_eq!(id_at(&terrain, 16, 16), 5);
+        assert_eq!(id_at(&terrain, 19, 16), 0);
+        assert_eq!(id_at(&terrain, 16, 26), 1);
+        // The soft edge keeps some of the 188 diggable texels within the radius
+        let dug = count(&terrain, 0) - (32 * 32 * 2 - 31 * 31);
+        assert!(dug > 9 && dug < 188, "{dug}");
+
+        // Harder materials come off less
+        let mut hard = self::terrain();
+        paint_square(&mut hard, Vector2I { x: 16, y: 16 }, 16, 1);
+        dig(&mut hard, Vector2I { x: 16, y: 16 }, 9, &[(1, 4.0)], 3);
+        let hard_dug = count(&hard, 0) - (32 * 32 * 2 - 31 * 31);
+        assert!(hard_dug > 0 && hard_dug < dug / 2, "{hard_dug}");
+    }
+
+    #[test]
+    fn copy_paste() {
+        let mut terrain = terrain();
+        paint_square(&mut terrain, Vector2I { x: 2, y: 2 }, 2, 3);
+        let selection = Selection::copy(&terrain, Vector2I { x: 4, y: 4 }, Vector2I { x: 0, y: 0 });
+        assert_eq!(selection.size, Vector2I { x: 5, y: 5 });
+        assert_eq!(
+            selection.texels.iter().filter(|id| **id == Some(3)).count(),
+            9
+        );
+
+        selection.paste(&mut terrain, Vector2I { x: 40, y: 10 });
+        assert_eq!(count(&terrain, 3), 18);
+        assert_eq!(id_at(&terrain, 41, 11), 3);
+        assert_eq!(id_at(&terrain, 40, 10), 0);
+
+        // Unloaded texels aren't pasted
+        let outside = Selection::copy(&terrain, Vector2I { x: 0, y: -2 }, Vector2I { x: 0, y: 0 });
+        assert_eq!(outside.texels, vec![None, None, Some(0)]);
+        outside.paste(&mut terrain, Vector2I { x: 10, y: 10 });
+        assert_eq!(terrain.chunk_iter().count(), 2);
+    }
+}
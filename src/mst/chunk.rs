@@ -75,3 +75,53 @@ impl Chunk {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Chunk;
+    use crate::{
+        mst::{
+            texel::{NeighbourMask, Texel},
+            utils::texel_index_to_local,
+        },
+        util::Vector2I,
+    };
+
+    /// Neighbour mask computed from the ids alone, texels outside of the chunk count as empty
+    fn recomputed_mask(chunk: &Chunk, local: Vector2I) -> NeighbourMask {
+        let mut mask: NeighbourMask = 0;
+        for (i, offset) in Texel::NEIGHBOUR_OFFSET_VECTORS.iter().enumerate() {
+            match chunk.get_texel(&(local + *offset)) {
+                Some(neighbour) if !neighbour.is_empty() => mask |= 1 << i,
+                _ => (),
+            }
+        }
+        mask
+    }
+
+    #[test]
+    fn neighbour_masks_after_edits() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..50 {
+            let mut chunk = Chunk::new();
+            // Few ids, so texels are often replaced by another solid id or set to the id they already have
+            for _ in 0..rng.gen_range(1..2000) {
+                let position = Vector2I {
+                    x: rng.gen_range(0..Chunk::SIZE.x),
+                    y: rng.gen_range(0..Chunk::SIZE.y),
+                };
+                chunk.set_texel(&position, rng.gen_range(0..3));
+            }
+            for i in 0..chunk.texels.len() {
+                let local = texel_index_to_local(i);
+                assert_eq!(
+                    chunk.texels[i].neighbour_mask,
+                    recomputed_mask(&chunk, local),
+                    "texel {local:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashSet;
+
+use super::{
+    chunk::Chunk,
+    texel::{Texel, TexelID},
+};
+use crate::{
+    resources::{Materials, Terrain},
+    util::Vector2I,
+};
+
+/// Largest fragment that can break off. Anything bigger is treated as anchored, so that
+/// meshing a fragment stays cheap enough to do during a frame.
+pub const MAX_FRAGMENT_SIZE: Vector2I = Vector2I {
+    x: Chunk::SIZE.x * 2,
+    y: Chunk::SIZE.y * 2,
+};
+
+/// Rigid terrain region that isn't connected to anything that holds it up
+pub struct Fragment {
+    /// Global positions and ids
+    pub texels: Vec<(Vector2I, TexelID)>,
+    pub min: Vector2I,
+    pub max: Vector2I,
+}
+
+impl Fragment {
+    pub fn size(&self) -> Vector2I {
+        self.max - self.min + Vector2I::ONE
+    }
+
+    /// Size of the fragment with an empty border of one texel around it
+    pub fn grid_size(&self) -> Vector2I {
+        self.size() + Vector2I::ONE * 2
+    }
+
+    /// Ids in rows of `grid_size`, the fragment's `min` lands at (1, 1)
+    pub fn to_grid(&self) -> Vec<TexelID> {
+        let size = self.grid_size();
+        let mut grid: Vec<TexelID> = vec![Texel::EMPTY; (size.x * size.y) as usize];
+        for (global, id) in self.texels.iter() {
+            let local = *global - self.min + Vector2I::ONE;
+            grid[(local.y * size.x + local.x) as usize] = *id;
+        }
+        grid
+    }
+}
+
+/// Find the fragments that contain any of the seed positions.
+///
+/// A region is anchored if it touches an anchored material, a chunk that isn't loaded
+/// (including the edges of the world), or grows bigger than `MAX_FRAGMENT_SIZE`.
+pub fn find_fragments(
+    terrain: &Terrain,
+    materials: &Materials,
+    seeds: &[Vector2I],
+) -> Vec<Fragment> {
+    let mut visited: HashSet<Vector2I> = HashSet::new();
+    let mut fragments: Vec<Fragment> = Vec::new();
+    for seed in seeds {
+        match find_fragment(terrain, materials, seed, &mut visited) {
+            Some(fragment) => fragments.push(fragment),
+            None => (),
+        }
+    }
+    fragments
+}
+
+/// Flood the rigid region around `seed` and return it if nothing holds it up.
+///
+/// The search stops as soon as the region is anchored or too big. Every position it reached is added to
+/// `visited`, which makes later seeds in the region return right away. Reaching a position an
+/// earlier search visited means the region is anchored, since free regions are always visited whole.
+fn find_fragment(
+    terrain: &Terrain,
+    materials: &Materials,
+    seed: &Vector2I,
+    visited: &mut HashSet<Vector2I>,
+) -> Option<Fragment> {
+    if visited.contains(seed) {
+        return None;
+    }
+    match terrain.global_to_texel(seed) {
+        Some(texel) if materials.is_rigid(texel.id) => (),
+        _ => return None,
+    }
+
+    let mut fragment = Fragment {
+        texels: Vec::new(),
+        min: *seed,
+        max: *seed,
+    };
+    let mut anchored = false;
+    let mut reached: HashSet<Vector2I> = HashSet::from([*seed]);
+    let mut stack: Vec<Vector2I> = vec![*seed];
+    'search: while let Some(global) = stack.pop() {
+        let id = terrain.global_to_texel(&global).unwrap().id;
+        if materials.is_anchored(id) {
+            anchored = true;
+            break;
+        }
+        fragment.texels.push((global, id));
+        fragment.min = Vector2I {
+            x: fragment.min.x.min(global.x),
+            y: fragment.min.y.min(global.y),
+        };
+        fragment.max = Vector2I {
+            x: fragment.max.x.max(global.x),
+            y: fragment.max.y.max(global.y),
+        };
+        let size = fragment.size();
+        if size.x > MAX_FRAGMENT_SIZE.x || size.y > MAX_FRAGMENT_SIZE.y {
+            anchored = true;
+            break;
+        }
+
+        for offset in Texel::NEIGHBOUR_OFFSET_VECTORS {
+            let neighbour = global + offset;
+            match terrain.global_to_texel(&neighbour) {
+                Some(texel) => {
+                    if !materials.is_rigid(texel.id) {
+                        continue;
+                    }
+                    if visited.contains(&neighbour) {
+                        anchored = true;
+                        break 'search;
+                    }
+                    if reached.insert(neighbour) {
+                        stack.push(neighbour);
+                    }
+                }
+                // Touches an unloaded chunk or the edge of the world
+                None => {
+                    anchored = true;
+                    break 'search;
+                }
+            }
+        }
+    }
+    visited.extend(reached);
+    match anchored {
+        true => None,
+        false => Some(fragment),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::{find_fragment, find_fragments, MAX_FRAGMENT_SIZE};
+    use crate::{
+        mst::chunk::Chunk,
+        resources::{Materials, Terrain},
+        util::Vector2I,
+    };
+
+    fn terrain_with_floor(materials: &Materials) -> Terrain {
+        let mut terrain = Terrain::from_chunks(HashMap::from([
+            (Vector2I::ZERO, Chunk::new()),
+            (Vector2I::RIGHT, Chunk::new()),
+        ]));
+        let dirt = materials.find("dirt").unwrap();
+        for x in 0..Chunk::SIZE.x * 2 {
+            terrain.set_texel(
+                &Vector2I {
+                    x,
+                    y: Chunk::SIZE.y - 1,
+                },
+                dirt,
+            );
+        }
+        terrain
+    }
+
+    #[test]
+    fn floating_block() {
+        let materials = Materials::default();
+        let mut terrain = terrain_with_floor(&materials);
+        let dirt = materials.find("dirt").unwrap();
+        // A block that crosses the chunk border
+        for y in 4..8 {
+            for x in 30..36 {
+                terrain.set_texel(&Vector2I { x, y }, dirt);
+            }
+        }
+
+        let seeds = [Vector2I { x: 31, y: 5 }, Vector2I { x: 35, y: 7 }];
+        let fragments = find_fragments(&terrain, &materials, &seeds);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].texels.len(), 24);
+        assert_eq!(fragments[0].size(), Vector2I { x: 6, y: 4 });
+        // The floor touches the edge of the world
+        let floor = [Vector2I {
+            x: 10,
+            y: Chunk::SIZE.y - 1,
+        }];
+        assert!(find_fragments(&terrain, &materials, &floor).is_empty());
+    }
+
+    #[test]
+    fn larger_than_chunk() {
+        let materials = Materials::default();
+        let mut terrain = terrain_with_floor(&materials);
+        let dirt = materials.find("dirt").unwrap();
+        for y in 4..8 {
+            for x in 5..45 {
+                terrain.set_texel(&Vector2I { x, y }, dirt);
+            }
+        }
+
+        let seeds = [Vector2I { x: 5, y: 4 }];
+        let fragments = find_fragments(&terrain, &materials, &seeds);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].size(), Vector2I { x: 40, y: 4 });
+        let grid = fragments[0].to_grid();
+        assert_eq!(grid.len(), 42 * 6);
+        assert_eq!(grid.iter().filter(|id| **id == dirt).count(), 160);
+    }
+
+    #[test]
+    fn too_big() {
+        let materials = Materials::default();
+        let mut terrain = Terrain::from_chunks(HashMap::from_iter(
+            (0..4).map(|x| (Vector2I { x, y: 0 }, Chunk::new())),
+        ));
+        let dirt = materials.find("dirt").unwrap();
+        for x in 1..MAX_FRAGMENT_SIZE.x + 2 {
+            terrain.set_texel(&Vector2I { x, y: 10 }, dirt);
+        }
+
+        let seeds = [Vector2I { x: 1, y: 10 }];
+        assert!(find_fragments(&terrain, &materials, &seeds).is_empty());
+    }
+
+    #[test]
+    fn anchored_material() {
+        let materials = Materials::default();
+        let mut terrain = terrain_with_floor(&materials);
+        let dirt = materials.find("dirt").unwrap();
+        let bedrock = materials.find("bedrock").unwrap();
+        for y in 10..14 {
+            terrain.set_texel(&Vector2I { x: 10, y }, dirt);
+        }
+        terrain.set_texel(&Vector2I { x: 11, y: 13 }, bedrock);
+
+        let seeds = [Vector2I { x: 10, y: 10 }];
+        assert!(find_fragments(&terrain, &materials, &seeds).is_empty());
+    }
+
+    #[test]
+    fn seeds_in_anchored_region() {
+        let materials = Materials::default();
+        let mut terrain = terrain_with_floor(&materials);
+        let dirt = materials.find("dirt").unwrap();
+        let bedrock = materials.find("bedrock").unwrap();
+        // An overhang held up by bedrock at its left end
+        terrain.set_texel(&Vector2I { x: 5, y: 10 }, bedrock);
+        for x in 6..20 {
+            terrain.set_texel(&Vector2I { x, y: 10 }, dirt);
+        }
+
+        // The search from the first seed finds the bedrock right away, the second seed is still held up by it
+        let seeds = [Vector2I { x: 6, y: 10 }, Vector2I { x: 15, y: 10 }];
+        assert!(find_fragments(&terrain, &materials, &seeds).is_empty());
+    }
+
+    #[test]
+    fn stops_at_anchor() {
+        let materials = Materials::default();
+        let mut terrain = terrain_with_floor(&materials);
+        let dirt = materials.find("dirt").unwrap();
+        let bedrock = materials.find("bedrock").unwrap();
+        terrain.set_texel(&Vector2I { x: 5, y: 10 }, bedrock);
+        for x in 6..60 {
+            terrain.set_texel(&Vector2I { x, y: 10 }, dirt);
+        }
+
+        // The search from next to the bedrock doesn't walk the rest of the overhang
+        let mut visited = HashSet::new();
+        assert!(find_fragment(
+            &terrain,
+            &materials,
+            &Vector2I { x: 6, y: 10 },
+            &mut visited
+        )
+        .is_none());
+        assert!(visited.len() < 4);
+        // A search from the other end stops where the first one left off
+        assert!(find_fragment(
+            &terrain,
+            &materials,
+            &Vector2I { x: 59, y: 10 },
+            &mut visited
+        )
+        .is_none());
+        assert!(visited.len() <= 55);
+        // A free block next to it is still found
+        for x in 20..24 {
+            terrain.set_texel(&Vector2I { x, y: 4 }, dirt);
+        }
+        let fragment = find_fragment(
+            &terrain,
+            &materials,
+            &Vector2I { x: 21, y: 4 },
+            &mut visited,
+        );
+        assert_eq!(fragment.unwrap().texels.len(), 4);
+    }
+}
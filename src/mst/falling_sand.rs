@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+
+use super::{
+    chunk::Chunk,
+    texel::TexelID,
+    utils::{global_to_index, index_to_global},
+};
+use crate::{
+    resources::{MaterialBehaviour, Materials, Terrain},
+    util::Vector2I,
+};
+
+/// Step the powder and liquid texels of the given chunks once.
+///
+/// Texels only move into loaded chunks. Chunks are processed bottom-up so that falling texels
+/// move once per tick, and the preferred sideways direction alternates with the tick for symmetric piles.
+///
+/// Returns the chunks in which texels moved.
+pub fn step(
+    terrain: &mut Terrain,
+    materials: &Materials,
+    chunks: &HashSet<Vector2I>,
+    tick: u64,
+) -> HashSet<Vector2I> {
+    let mut indices: Vec<Vector2I> = chunks
+        .iter()
+        .filter(|index| terrain.is_chunk_loaded(index))
+        .cloned()
+        .collect();
+    indices.sort_by_key(|index| (-index.y, index.x));
+
+    let mut moved_chunks: HashSet<Vector2I> = HashSet::new();
+    // Destinations of this tick, a texel may only move once per tick
+    let mut moved: HashSet<Vector2I> = HashSet::new();
+    for index in indices {
+        let origin = index_to_global(&index);
+        for y in (0..Chunk::SIZE.y).rev() {
+            for i in 0..Chunk::SIZE.x {
+                let x = if tick % 2 == 0 {
+                    i
+                } else {
+                    Chunk::SIZE.x - 1 - i
+                };
+                let global = origin + Vector2I { x, y };
+                if moved.contains(&global) {
+                    continue;
+                }
+                let id = match terrain.global_to_texel(&global) {
+                    Some(texel) => texel.id,
+                    None => continue,
+                };
+                let target = match materials.behaviour(id) {
+                    MaterialBehaviour::Static => continue,
+                    behaviour => find_target(terrain, materials, &global, id, behaviour, tick),
+                };
+                match target {
+                    Some((target, target_id)) => {
+                        terrain.set_texel(&target, id);
+                        terrain.set_texel(&global, target_id);
+                        moved.insert(target);
+                        moved_chunks.insert(index);
+                        moved_chunks.insert(global_to_index(&target));
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+    moved_chunks
+}
+
+/// Position and id of the texel that the texel at `global` swaps with
+fn find_target(
+    terrain: &Terrain,
+    materials: &Materials,
+    global: &Vector2I,
+    id: TexelID,
+    behaviour: MaterialBehaviour,
+    tick: u64,
+) -> Option<(Vector2I, TexelID)> {
+    // Alternate the preferred side per tick and column. The parity of a sum is the parity of the xor,
+    // which doesn't overflow for negative coordinates.
+    let side = if (tick ^ (global.x ^ global.y) as u64) & 1 == 0 {
+        Vector2I::LEFT
+    } else {
+        Vector2I::RIGHT
+    };
+    let mut candidates = vec![Vector2I::DOWN, Vector2I::DOWN + side, Vector2I::DOWN - side];
+    if behaviour == MaterialBehaviour::Liquid {
+        candidates.push(side);
+        candidates.push(-side);
+    }
+
+    for offset in candidates {
+        let target = *global + offset;
+        // Diagonal moves must not squeeze through a gap between two corners
+        if offset.x != 0 && offset.y != 0 {
+            let side_texel = *global + Vector2I { x: offset.x, y: 0 };
+            if !can_displace(terrain, materials, &side_texel, id) {
+                continue;
+            }
+        }
+        if can_displace(terrain, materials, &target, id) {
+            match terrain.global_to_texel(&target) {
+                Some(texel) => return Some((target, texel.id)),
+                None => (),
+            }
+        }
+    }
+    None
+}
+
+/// Whether a texel of material `id` can move into `global`
+fn can_displace(terrain: &Terrain, materials: &Materials, global: &Vector2I, id: TexelID) -> bool {
+    match terrain.global_to_texel(global) {
+        Some(texel) => {
+            !materials.is_solid(texel.id) && materials.density(texel.id) < materials.density(id)
+        }
+        None => false,
+    }
+}
+
+/// Index of every loaded chunk that contains texels which might move
+pub fn active_chunks(terrain: &Terrain, materials: &Materials) -> HashSet<Vector2I> {
+    terrain
+        .chunk_iter()
+        .filter(|(_, chunk)| {
+            chunk
+                .texels
+                .iter()
+                .any(|texel| materials.behaviour(texel.id) != MaterialBehaviour::Static)
+        })
+        .map(|(index, _)| *index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::step;
+    use crate::{
+        mst::{chunk::Chunk, texel::Texel},
+        resources::{Materials, Terrain},
+        util::Vector2I,
+    };
+
+    fn run(terrain: &mut Terrain, materials: &Materials, ticks: u64) {
+        let chunks: HashSet<Vector2I> = terrain.chunk_iter().map(|(index, _)| *index).collect();
+        for tick in 0..ticks {
+            step(terrain, materials, &chunks, tick);
+        }
+    }
+
+    fn count(terrain: &Terrain, id: u8) -> usize {
+        terrain
+            .chunk_iter()
+            .map(|(_, chunk)| chunk.texels.iter().filter(|texel| texel.id == id).count())
+            .sum()
+    }
+
+    #[test]
+    fn sand_falls_and_piles() {
+        let materials = Materials::default();
+        let sand = materials.find("sand").unwrap();
+        let mut terrain = Terrain::from_chunks(HashMap::from([(Vector2I::ZERO, Chunk::new())]));
+        for y in 0..5 {
+            terrain.set_texel(&Vector2I { x: 16, y }, sand);
+        }
+
+        run(&mut terrain, &materials, 64);
+
+        assert_eq!(count(&terrain, sand), 5);
+        let bottom = Chunk::SIZE.y - 1;
+        assert_eq!(
+            terrain
+                .global_to_texel(&Vector2I { x: 16, y: bottom })
+                .unwrap()
+                .id,
+            sand
+        );
+        // The column collapses into a pile instead of standing upright
+        assert_eq!(
+            terrain
+                .global_to_texel(&Vector2I {
+                    x: 16,
+                    y: bottom - 4
+                })
+                .unwrap()
+                .id,
+            Texel::EMPTY
+        );
+    }
+
+    #[test]
+    fn sand_falls_in_negative_chunks() {
+        let materials = Materials::default();
+        let sand = materials.find("sand").unwrap();
+        let index = Vector2I { x: -1, y: -1 };
+        let mut terrain = Terrain::from_chunks(HashMap::from([(index, Chunk::new())]));
+        for y in -Chunk::SIZE.y..-Chunk::SIZE.y + 5 {
+            terrain.set_texel(&Vector2I { x: -16, y }, sand);
+        }
+
+        run(&mut terrain, &materials, 64);
+
+        assert_eq!(count(&terrain, sand), 5);
+        assert_eq!(
+            terrain
+                .global_to_texel(&Vector2I { x: -16, y: -1 })
+                .unwrap()
+                .id,
+            sand
+        );
+    }
+
+    #[test]
+    fn water_flows_sideways() {
+        let materials = Materials::default();
+        let water = materials.find("water").unwrap();
+        let stone = materials.find("stone").unwrap();
+        let mut terrain = Terrain::from_chunks(HashMap::from([(Vector2I::ZERO, Chunk::new())]));
+        for x in 0..Chunk::SIZE.x {
+            terrain.set_texel(&Vector2I { x, y: 20 }, stone);
+        }
+        for y in 10..14 {
+            terrain.set_texel(&Vector2I { x: 16, y }, water);
+        }
+
+        run(&mut terrain, &materials, 128);
+
+        assert_eq!(count(&terrain, water), 4);
+        // All of the water rests in a single layer on the floor
+        for x in 0..Chunk::SIZE.x {
+            for y in 0..19 {
+                assert_ne!(
+                    terrain.global_to_texel(&Vector2I { x, y }).unwrap().id,
+                    water
+                );
+            }
+        }
+    }
+}
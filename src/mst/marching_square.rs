@@ -1,40 +1,56 @@
-use super::{chunk::Chunk, utils::texel_index_to_local};
-use crate::util::{Segment2I, Vector2F, Vector2I};
+use super::{
+    chunk::Chunk,
+    simplify::{simplify_chain, simplify_loop},
+    texel::{Texel, TexelID},
+    utils::{index_to_global, local_to_texel_index},
+};
+use crate::{
+    resources::{Materials, Terrain},
+    util::{
+        polygon::{bounds, segments_cross, signed_area},
+        Segment2I, Vector2F, Vector2I,
+    },
+};
 use lazy_static::lazy_static;
-use std::collections::VecDeque;
+use specs::rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 
-type Island = VecDeque<Segment2I>;
+type Island = Vec<Segment2I>;
+
+/// Texel id at a position relative to the area being meshed, `None` where nothing is loaded
+pub type Sampler<'a> = dyn Fn(Vector2I) -> Option<TexelID> + 'a;
 
 lazy_static! {
-    /// Marching Square case dictionary.
-    ///
-    /// Key is a bitmask of neighbouring tiles (up, right, down, left - least significant bit first).
-    /// Bit set to 1 means that the neighbour has collision. Only the 4 least significant bits are currently used.
+    /// Marching Square case dictionary for corner sampling, used by `MarchingMode::Interpolated`.
     ///
-    /// Value is an array of segments that the tile should have. The segments are configured to go clockwise.
+    /// Key is a bitmask of the cell's corners (top left, top right, bottom right, bottom left - least significant bit first),
+    /// where the corners are texel centers. Bit set to 1 means that the texel has collision.
     ///
-    /// Note: This dictionary should only be used for empty tiles.
-    static ref MST_CASE_MAP: [Vec<Segment2I>; 16] = [
+    /// Value is an array of sides between the midpoints of the cell edges (0 top, 1 right, 2 bottom, 3 left),
+    /// going the same direction as in `MST_SIDE_MAP`. The saddle cases are separated, as diagonal texels aren't connected.
+    static ref MST_INTERPOLATED_CASE_MAP: [Vec<(usize, usize)>; 16] = [
         /* 0b0000 */ vec![],
-        /* 0b0001 */ vec![ Segment2I { from: Vector2I::RIGHT, to: Vector2I::ZERO } ],
-        /* 0b0010 */ vec![ Segment2I { from: Vector2I::ONE, to: Vector2I::RIGHT } ],
-        /* 0b0011 */ vec![ Segment2I { from: Vector2I::ONE, to: Vector2I::ZERO } ],
-        /* 0b0100 */ vec![ Segment2I { from: Vector2I::DOWN, to: Vector2I::ONE } ],
-        /* 0b0101 */ vec![ Segment2I { from: Vector2I::RIGHT, to: Vector2I::ZERO }, Segment2I { from: Vector2I::DOWN, to: Vector2I::ONE } ],
-        /* 0b0110 */ vec![ Segment2I { from: Vector2I::DOWN, to: Vector2I::RIGHT } ],
-        /* 0b0111 */ vec![ Segment2I { from: Vector2I::DOWN, to: Vector2I::ZERO } ],
-        /* 0b1000 */ vec![ Segment2I { from: Vector2I::ZERO, to: Vector2I::DOWN } ],
-        /* 0b1001 */ vec![ Segment2I { from: Vector2I::RIGHT, to: Vector2I::DOWN } ],
-        /* 0b1010 */ vec![ Segment2I { from: Vector2I::ONE, to: Vector2I::RIGHT }, Segment2I { from: Vector2I::ZERO, to: Vector2I::DOWN } ],
-        /* 0b1011 */ vec![ Segment2I { from: Vector2I::ONE, to: Vector2I::DOWN } ],
-        /* 0b1100 */ vec![ Segment2I { from: Vector2I::ZERO, to: Vector2I::ONE } ],
-        /* 0b1101 */ vec![ Segment2I { from: Vector2I::RIGHT, to: Vector2I::ONE } ],
-        /* 0b1110 */ vec![ Segment2I { from: Vector2I::ZERO, to: Vector2I::RIGHT } ],
+        /* 0b0001 */ vec![ (0, 3) ],
+        /* 0b0010 */ vec![ (1, 0) ],
+        /* 0b0011 */ vec![ (1, 3) ],
+        /* 0b0100 */ vec![ (2, 1) ],
+        /* 0b0101 */ vec![ (0, 3), (2, 1) ],
+        /* 0b0110 */ vec![ (2, 0) ],
+        /* 0b0111 */ vec![ (2, 3) ],
+        /* 0b1000 */ vec![ (3, 2) ],
+        /* 0b1001 */ vec![ (0, 2) ],
+        /* 0b1010 */ vec![ (1, 0), (3, 2) ],
+        /* 0b1011 */ vec![ (1, 2) ],
+        /* 0b1100 */ vec![ (3, 1) ],
+        /* 0b1101 */ vec![ (0, 1) ],
+        /* 0b1110 */ vec![ (3, 0) ],
         /* 0b1111 */ vec![],
     ];
 
-    /// Version of the MS case dictionary that is used by the solid tiles at the edge of the chunk
-    static ref MST_EDGE_CASE_MAP: [Segment2I; 4] = [
+    /// Sides of a solid texel, used for every neighbour in `Texel::NEIGHBOUR_OFFSET_VECTORS` without collision.
+    ///
+    /// The sides go clockwise, with the solid texel on the right.
+    static ref MST_SIDE_MAP: [Segment2I; 4] = [
         /* up    */ Segment2I { from: Vector2I::ZERO, to: Vector2I::RIGHT },
         /* right */ Segment2I { from: Vector2I::RIGHT, to: Vector2I::ONE },
         /* down  */ Segment2I { from: Vector2I::ONE, to: Vector2I::DOWN },
@@ -42,134 +58,1216 @@ lazy_static! {
     ];
 }
 
-pub fn calculate_collisions(chunk: &Chunk) -> Vec<Vec<Vector2F>> {
+/// Half texel coordinates keep the interpolated midpoints on integers
+const INTERPOLATED_SCALE: i32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MarchingMode {
+    /// Outlines follow the texel edges
+    #[default]
+    Blocky,
+    /// Outlines connect the midpoints between texel centers, giving diagonal slopes
+    Interpolated,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CollisionOptions {
+    pub mode: MarchingMode,
+    /// Simplify the outlines so that they deviate at most this many texels from the texel edges.
+    /// `None` keeps every corner.
+    pub simplify_tolerance: Option<f32>,
+}
+
+/// Outline of solid terrain in chunk local texel space
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outline {
+    /// Closed outlines repeat the first point at the end
+    pub points: Vec<Vector2F>,
+    /// Open outlines start and end on the chunk edge, and continue in a neighbouring chunk
+    pub closed: bool,
+    /// Points before the first and after the last point of an open outline, taken from the neighbouring chunks
+    pub ghost_vertices: Option<(Vector2F, Vector2F)>,
+    /// Most common solid material along the outline, which gives the surface its friction
+    pub material: TexelID,
+}
+
+/// Direction a closed outline goes around, on screen where y points down
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// What a closed outline goes around
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// Outside edge of solid terrain, wound clockwise
+    Outer,
+    /// Edge of a cave inside solid terrain, wound counter-clockwise
+    Hole,
+}
+
+/// Closed outline of a chunk in chunk local texel space, and what it goes around
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollisionIsland {
+    /// The first point is repeated at the end
+    pub points: Vec<Vector2F>,
+    pub winding: Winding,
+    pub boundary: Boundary,
+    /// Area inside the outline, including any holes
+    pub area: f32,
+    pub min: Vector2F,
+    pub max: Vector2F,
+    /// Sorted ids of the texels inside, up to any nested outline.
+    /// Colliding materials for outer boundaries, air and liquids for holes.
+    pub materials: Vec<TexelID>,
+}
+
+impl CollisionIsland {
+    fn new(points: Vec<Vector2F>) -> CollisionIsland {
+        let area = signed_area(&points);
+        let (winding, boundary) = match area >= 0.0 {
+            true => (Winding::Clockwise, Boundary::Outer),
+            false => (Winding::CounterClockwise, Boundary::Hole),
+        };
+        let (min, max) = bounds(&points);
+        CollisionIsland {
+            points,
+            winding,
+            boundary,
+            area: area.abs(),
+            min,
+            max,
+            materials: Vec::new(),
+        }
+    }
+}
+
+fn collides(sample: &Sampler, materials: &Materials, local: Vector2I) -> bool {
+    match sample(local) {
+        Some(id) => materials.collides(id),
+        None => false,
+    }
+}
+
+/// Whether `local` lies within an area of the given size
+fn is_inside(size: Vector2I, local: Vector2I) -> bool {
+    local.x >= 0 && local.y >= 0 && local.x < size.x && local.y < size.y
+}
+
+/// Whether the area being meshed owns geometry that depends on the given positions.
+///
+/// The first loaded position decides, so every side is meshed by exactly one chunk.
+fn is_owned(sample: &Sampler, size: Vector2I, positions: &[Vector2I]) -> Option<bool> {
+    positions
+        .iter()
+        .find(|local| sample(**local).is_some())
+        .map(|local| is_inside(size, *local))
+}
+
+/// Cells that can have sides when meshing an area of the given size, including the loaded cells around it.
+///
+/// Cells are texels when blocky, and the squares between four texel centers named after their top left corner
+/// when interpolated. Both ends are inclusive.
+fn cell_range(size: Vector2I, mode: MarchingMode) -> (Vector2I, Vector2I) {
+    match mode {
+        MarchingMode::Blocky => (Vector2I::ONE * -1, size),
+        MarchingMode::Interpolated => (Vector2I::ONE * -2, size),
+    }
+}
+
+/// Cells whose sides depend on the texel at `local`
+fn cells_around(local: Vector2I, mode: MarchingMode) -> Vec<Vector2I> {
+    match mode {
+        MarchingMode::Blocky => std::iter::once(local)
+            .chain(
+                Texel::NEIGHBOUR_OFFSET_VECTORS
+                    .iter()
+                    .map(|offset| local + *offset),
+            )
+            .collect(),
+        MarchingMode::Interpolated => vec![
+            local - Vector2I::ONE,
+            local - Vector2I::DOWN,
+            local - Vector2I::RIGHT,
+            local,
+        ],
+    }
+}
+
+/// Sides of a single cell, and whether they belong to the area or to the loaded cells around it.
+/// `None` if the cell has no sides.
+///
+/// Blocky sides follow the texel edges, see `MST_SIDE_MAP`. Interpolated sides go between texel centers,
+/// see `MST_INTERPOLATED_CASE_MAP`, with coordinates in half texels.
+fn cell_sides(
+    sample: &Sampler,
+    size: Vector2I,
+    materials: &Materials,
+    mode: MarchingMode,
+    cell: Vector2I,
+) -> Option<(bool, Vec<Segment2I>)> {
+    let mut sides: Vec<Segment2I> = Vec::new();
+    let owned = match mode {
+        MarchingMode::Blocky => {
+            if !collides(sample, materials, cell) {
+                return None;
+            }
+            for (i, offset) in Texel::NEIGHBOUR_OFFSET_VECTORS.iter().enumerate() {
+                if !collides(sample, materials, cell + *offset) {
+                    sides.push(Segment2I {
+                        from: MST_SIDE_MAP[i].from + cell,
+                        to: MST_SIDE_MAP[i].to + cell,
+                    });
+                }
+            }
+            is_inside(size, cell)
+        }
+        MarchingMode::Interpolated => {
+            let position = |local: Vector2I| local * INTERPOLATED_SCALE + Vector2I::ONE;
+            let corners = [
+                cell,
+                cell + Vector2I::RIGHT,
+                cell + Vector2I::ONE,
+                cell + Vector2I::DOWN,
+            ];
+            let case = corners
+                .iter()
+                .enumerate()
+                .map(|(i, corner)| (collides(sample, materials, *corner) as usize) << i)
+                .sum::<usize>();
+            if MST_INTERPOLATED_CASE_MAP[case].is_empty() {
+                return None;
+            }
+            let owned = is_owned(sample, size, &corners)?;
+            // Midpoints of the cell's top, right, bottom and left edges
+            let midpoints: [Vector2I; 4] =
+                [0, 1, 2, 3].map(|i| (position(corners[i]) + position(corners[(i + 1) % 4])) / 2);
+            for (from, to) in MST_INTERPOLATED_CASE_MAP[case].iter() {
+                sides.push(Segment2I {
+                    from: midpoints[*from],
+                    to: midpoints[*to],
+                });
+            }
+            owned
+        }
+    };
+    match sides.is_empty() {
+        true => None,
+        false => Some((owned, sides)),
+    }
+}
+
+/// Sides of every cell of the area and of the loaded cells around it, in row order
+fn area_sides(
+    sample: &Sampler,
+    size: Vector2I,
+    materials: &Materials,
+    mode: MarchingMode,
+) -> Vec<(Vector2I, bool, Vec<Segment2I>)> {
+    let (min, max) = cell_range(size, mode);
+    let mut result: Vec<(Vector2I, bool, Vec<Segment2I>)> = Vec::new();
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            let cell = Vector2I { x, y };
+            match cell_sides(sample, size, materials, mode, cell) {
+                Some((owned, sides)) => result.push((cell, owned, sides)),
+                None => (),
+            }
+        }
+    }
+    result
+}
+
+/// Connect sides that share end points into islands. Islands are either closed loops or open chains.
+///
+/// Contours are traced in a single pass, following each side to the one that starts where it ends.
+/// Open chains are traced from their first side, so they are never split.
+/// Where two texels only touch diagonally the contour turns towards the solid side, keeping them apart.
+fn assemble_islands(sides: Vec<Segment2I>) -> Vec<Island> {
+    let mut outgoing: HashMap<Vector2I, Vec<usize>> = HashMap::with_capacity(sides.len());
+    let mut incoming: HashMap<Vector2I, usize> = HashMap::with_capacity(sides.len());
+    for (i, side) in sides.iter().enumerate() {
+        outgoing.entry(side.from).or_default().push(i);
+        *incoming.entry(side.to).or_default() += 1;
+    }
+
+    let mut visited: Vec<bool> = vec![false; sides.len()];
     let mut islands: Vec<Island> = Vec::new();
-    for i in 0..chunk.texels.len() {
-        let local = texel_index_to_local(i);
+    // Open chains start where more sides leave than arrive, whatever is left forms closed loops
+    let chain_starts = (0..sides.len()).filter(|i| {
+        let from = sides[*i].from;
+        outgoing[&from].len() > incoming.get(&from).copied().unwrap_or(0)
+    });
+    for start in chain_starts.chain(0..sides.len()) {
+        if visited[start] {
+            continue;
+        }
+        let mut island: Island = Island::new();
+        let mut current = start;
+        loop {
+            visited[current] = true;
+            island.push(sides[current]);
+            match next_side(&sides, &outgoing, &visited, current, start) {
+                Some(next) if next != start => current = next,
+                _ => break,
+            }
+        }
+        islands.push(island);
+    }
+    islands
+}
+
+/// Side that continues `current`, preferring a right turn, or `start` if it closes the loop
+fn next_side(
+    sides: &[Segment2I],
+    outgoing: &HashMap<Vector2I, Vec<usize>>,
+    visited: &[bool],
+    current: usize,
+    start: usize,
+) -> Option<usize> {
+    let side = sides[current];
+    let direction = side.to - side.from;
+    // Clockwise with y pointing down
+    let right = Vector2I {
+        x: -direction.y,
+        y: direction.x,
+    };
+    let candidates = outgoing.get(&side.to)?;
+    let available = |i: &&usize| !visited[**i] || **i == start;
+    let turns_right = |i: &&usize| {
+        let next = sides[**i].to - sides[**i].from;
+        next.x * right.y - next.y * right.x == 0 && next.x * right.x + next.y * right.y > 0
+    };
+    candidates
+        .iter()
+        .filter(available)
+        .find(turns_right)
+        .or_else(|| candidates.iter().find(available))
+        .copied()
+}
+
+/// Texels on the solid (right) and the empty side of a side.
+///
+/// These are the nearest texel centers, the texels next to a blocky side or the cell corners when interpolated.
+/// `scale` as in `island_points`.
+fn side_texels(side: &Segment2I, scale: f32) -> (Vector2I, Vector2I) {
+    let direction = side.to - side.from;
+    let right = Vector2F::from(Vector2I {
+        x: -direction.y,
+        y: direction.x,
+    });
+    let midpoint = Vector2F::from(side.from + side.to) / (2.0 * scale);
+    let origin = Vector2I {
+        x: midpoint.x.floor() as i32,
+        y: midpoint.y.floor() as i32,
+    };
+    let nearest = |sign: f32| {
+        let mut nearest: Option<(f32, Vector2I)> = None;
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let texel = origin + Vector2I { x, y };
+                let offset = Vector2F::from(texel) + Vector2F::ONE * 0.5 - midpoint;
+                if (offset.x * right.x + offset.y * right.y) * sign <= 0.0 {
+                    continue;
+                }
+                let distance = offset.x * offset.x + offset.y * offset.y;
+                match nearest {
+                    Some((nearest_distance, _)) if nearest_distance <= distance => (),
+                    _ => nearest = Some((distance, texel)),
+                }
+            }
+        }
+        nearest.unwrap().1
+    };
+    (nearest(1.0), nearest(-1.0))
+}
+
+/// Sorted ids of the texels connected to `start` that collide the same way it does.
+///
+/// Colliding texels connect to their 4 neighbours, others also diagonally, like the outlines separate them.
+fn enclosed_materials(
+    sample: &Sampler,
+    size: Vector2I,
+    materials: &Materials,
+    start: Vector2I,
+) -> Vec<TexelID> {
+    let id_at = |local: Vector2I| match is_inside(size, local) {
+        true => sample(local),
+        false => None,
+    };
+    let solid = match id_at(start) {
+        Some(id) => materials.collides(id),
+        None => return vec![],
+    };
+    let diagonals = [
+        Vector2I { x: 1, y: -1 },
+        Vector2I { x: 1, y: 1 },
+        Vector2I { x: -1, y: 1 },
+        Vector2I { x: -1, y: -1 },
+    ];
+    let offsets: Vec<Vector2I> = match solid {
+        true => Texel::NEIGHBOUR_OFFSET_VECTORS.to_vec(),
+        false => Texel::NEIGHBOUR_OFFSET_VECTORS
+            .iter()
+            .chain(diagonals.iter())
+            .copied()
+            .collect(),
+    };
+
+    let mut visited: Vec<bool> = vec![false; (size.x * size.y) as usize];
+    let mut stack: Vec<Vector2I> = vec![start];
+    let mut ids: Vec<TexelID> = Vec::new();
+    while let Some(local) = stack.pop() {
+        let (i, id) = match id_at(local) {
+            Some(id) => ((local.y * size.x + local.x) as usize, id),
+            None => continue,
+        };
+        if visited[i] || materials.collides(id) != solid {
+            continue;
+        }
+        visited[i] = true;
+        ids.push(id);
+        for offset in offsets.iter() {
+            stack.push(local + *offset);
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Most common material on the solid side of the island's sides
+fn surface_material(island: &Island, scale: f32, sample: &Sampler) -> TexelID {
+    let mut counts: HashMap<TexelID, usize> = HashMap::new();
+    for side in island {
+        match sample(side_texels(side, scale).0) {
+            Some(id) => *counts.entry(id).or_default() += 1,
+            None => (),
+        }
+    }
+    // Ties go to the lower id, so the result doesn't depend on the hash order
+    counts
+        .into_iter()
+        .max_by_key(|(id, count)| (*count, std::cmp::Reverse(*id)))
+        .map(|(id, _)| id)
+        .unwrap_or(Texel::EMPTY)
+}
+
+/// Island points with collinear sides merged
+fn island_points(island: &Island, scale: f32) -> Vec<Vector2F> {
+    let mut points: Vec<Vector2F> = Vec::with_capacity(island.len() + 1);
+    points.push(Vector2F::from(island.first().unwrap().from) / scale);
+    let mut current_angle: Option<f32> = None;
+    for side in island {
+        if current_angle.is_some() && (current_angle.unwrap() - side.angle()).abs() < 0.1 {
+            let len = points.len();
+            points[len - 1] = Vector2F::from(side.to) / scale
+        } else {
+            current_angle = Some(side.angle());
+            points.push(Vector2F::from(side.to) / scale);
+        }
+    }
+    points
+}
+
+fn scale(mode: MarchingMode) -> f32 {
+    match mode {
+        MarchingMode::Blocky => 1.0,
+        MarchingMode::Interpolated => INTERPOLATED_SCALE as f32,
+    }
+}
+
+/// Outline of an island. Open islands continue in `foreign_sides`, the sides around the area.
+fn island_outline(
+    island: &Island,
+    scale: f32,
+    sample: &Sampler,
+    foreign_sides: &[Segment2I],
+) -> Outline {
+    let (first, last) = (island.first().unwrap(), island.last().unwrap());
+    let closed = first.from == last.to;
+    let ghost_vertices = match closed {
+        true => None,
+        false => {
+            let prev = foreign_sides.iter().find(|side| side.to == first.from);
+            let next = foreign_sides.iter().find(|side| side.from == last.to);
+            match (prev, next) {
+                (Some(prev), Some(next)) => Some((
+                    Vector2F::from(prev.from) / scale,
+                    Vector2F::from(next.to) / scale,
+                )),
+                _ => None,
+            }
+        }
+    };
+    Outline {
+        points: island_points(island, scale),
+        closed,
+        ghost_vertices,
+        material: surface_material(island, scale, sample),
+    }
+}
+
+/// Simplify the outlines from `start` on, around all of the outlines as they are at that point,
+/// so that none of them cross
+fn simplify_outlines(
+    outlines: &mut [Outline],
+    start: usize,
+    tolerance: f32,
+    sample: &Sampler,
+    materials: &Materials,
+) {
+    let is_solid = |texel: Vector2I| collides(sample, materials, texel);
+    // Simplifying only removes points, so the bounds stay around the outlines
+    let outline_bounds: Vec<(Vector2F, Vector2F)> = outlines
+        .iter()
+        .map(|outline| bounds(&outline.points))
+        .collect();
+    for i in start..outlines.len() {
+        let crosses_other = |a: Vector2F, b: Vector2F| {
+            outlines.iter().enumerate().any(|(j, other)| {
+                let (min, max) = outline_bounds[j];
+                j != i
+                    && a.x.max(b.x) >= min.x
+                    && a.x.min(b.x) <= max.x
+                    && a.y.max(b.y) >= min.y
+                    && a.y.min(b.y) <= max.y
+                    && other
+                        .points
+                        .windows(2)
+                        .any(|side| segments_cross((a, b), (side[0], side[1])))
+            })
+        };
+        let outline = &outlines[i];
+        let points = match outline.closed {
+            true => simplify_loop(&outline.points, tolerance, &is_solid, &crosses_other),
+            false => simplify_chain(&outline.points, tolerance, &is_solid, &crosses_other),
+        };
+        outlines[i].points = points;
+    }
+}
+
+/// Outlines with the texels on either side of their first side, see `side_texels`
+fn calculate_outlines(
+    sample: &Sampler,
+    size: Vector2I,
+    materials: &Materials,
+    options: &CollisionOptions,
+) -> Vec<(Outline, (Vector2I, Vector2I))> {
+    let scale = scale(options.mode);
+    let (mut sides, mut foreign_sides): (Vec<Segment2I>, Vec<Segment2I>) = (Vec::new(), Vec::new());
+    for (_, owned, cell_sides) in area_sides(sample, size, materials, options.mode) {
+        match owned {
+            true => sides.extend(cell_sides),
+            false => foreign_sides.extend(cell_sides),
+        }
+    }
+
+    let islands = assemble_islands(sides);
+    let mut outlines: Vec<Outline> = islands
+        .iter()
+        .map(|island| island_outline(island, scale, sample, &foreign_sides))
+        .collect();
+    match options.simplify_tolerance {
+        Some(tolerance) => simplify_outlines(&mut outlines, 0, tolerance, sample, materials),
+        None => (),
+    }
+    outlines
+        .into_iter()
+        .zip(islands.iter())
+        .map(|(outline, island)| (outline, side_texels(island.first().unwrap(), scale)))
+        .collect()
+}
+
+/// Closed outlines of a chunk on its own, with everything outside of it empty
+pub fn calculate_collisions(
+    chunk: &Chunk,
+    materials: &Materials,
+    options: &CollisionOptions,
+) -> Vec<CollisionIsland> {
+    let sample = |local: Vector2I| local_to_texel_index(&local).map(|i| chunk.texels[i].id);
+    calculate_area_collisions(&sample, Chunk::SIZE, materials, options)
+}
+
+/// Closed outlines of an area of any size, with everything outside of it empty.
+///
+/// `sample` is only called for positions within the area.
+pub fn calculate_area_collisions(
+    sample: &Sampler,
+    size: Vector2I,
+    materials: &Materials,
+    options: &CollisionOptions,
+) -> Vec<CollisionIsland> {
+    let sample = |local: Vector2I| match is_inside(size, local) {
+        true => sample(local),
+        false => None,
+    };
+    calculate_outlines(&sample, size, materials, options)
+        .into_iter()
+        .map(|(outline, (solid, empty))| {
+            let mut island = CollisionIsland::new(outline.points);
+            let inside = match island.boundary {
+                Boundary::Outer => solid,
+                Boundary::Hole => empty,
+            };
+            island.materials = enclosed_materials(&sample, size, materials, inside);
+            island
+        })
+        .collect()
+}
 
-        let edge_mask: u8 = if local.y == 0 { 1 << 0 } else { 0 }
-            | if local.x == Chunk::SIZE.x - 1 {
-                1 << 1
+/// Texel ids around a terrain chunk, relative to it. `None` if the chunk isn't loaded.
+fn chunk_sampler<'a>(
+    terrain: &'a Terrain,
+    index: &Vector2I,
+) -> Option<impl Fn(Vector2I) -> Option<TexelID> + 'a> {
+    let chunk = terrain.index_to_chunk(index)?;
+    let origin = index_to_global(index);
+    Some(move |local: Vector2I| match local_to_texel_index(&local) {
+        Some(i) => Some(chunk.texels[i].id),
+        None => terrain
+            .global_to_texel(&(origin + local))
+            .map(|texel| texel.id),
+    })
+}
+
+/// Outlines of a terrain chunk, with the sides they were traced from so that edits only re-trace
+/// the outlines around them. See `calculate_terrain_collisions`.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkContours {
+    pub outlines: Vec<Outline>,
+    /// Sides of each outline, before collinear sides are merged and the outline is simplified
+    islands: Vec<Island>,
+    /// Sides of the chunk's own cells, see `cell_sides`
+    own: HashMap<Vector2I, Vec<Segment2I>>,
+    /// Sides of the loaded cells around the chunk, where its open outlines continue
+    foreign: HashMap<Vector2I, Vec<Segment2I>>,
+}
+
+impl ChunkContours {
+    pub fn new(
+        terrain: &Terrain,
+        index: &Vector2I,
+        materials: &Materials,
+        options: &CollisionOptions,
+    ) -> ChunkContours {
+        let sample = match chunk_sampler(terrain, index) {
+            Some(sample) => sample,
+            None => return ChunkContours::default(),
+        };
+        let mut contours = ChunkContours::default();
+        let mut sides: Vec<Segment2I> = Vec::new();
+        for (cell, owned, cell_sides) in area_sides(&sample, Chunk::SIZE, materials, options.mode) {
+            if owned {
+                sides.extend(cell_sides.iter().copied());
+                contours.own.insert(cell, cell_sides);
             } else {
-                0
+                contours.foreign.insert(cell, cell_sides);
+            }
+        }
+        contours.add_islands(assemble_islands(sides), &sample, materials, options);
+        contours
+    }
+
+    /// Sides around the chunk in row order, like `area_sides` lists them
+    fn foreign_sides(&self) -> Vec<Segment2I> {
+        let mut cells: Vec<(&Vector2I, &Vec<Segment2I>)> = self.foreign.iter().collect();
+        cells.sort_by_key(|(cell, _)| (cell.y, cell.x));
+        cells
+            .into_iter()
+            .flat_map(|(_, sides)| sides.iter().copied())
+            .collect()
+    }
+
+    fn add_islands(
+        &mut self,
+        islands: Vec<Island>,
+        sample: &Sampler,
+        materials: &Materials,
+        options: &CollisionOptions,
+    ) {
+        let (start, scale, foreign_sides) = (
+            self.outlines.len(),
+            scale(options.mode),
+            self.foreign_sides(),
+        );
+        for island in islands {
+            self.outlines
+                .push(island_outline(&island, scale, sample, &foreign_sides));
+            self.islands.push(island);
+        }
+        match options.simplify_tolerance {
+            Some(tolerance) => {
+                simplify_outlines(&mut self.outlines, start, tolerance, sample, materials)
             }
-            | if local.y == Chunk::SIZE.y - 1 {
-                1 << 2
+            None => (),
+        }
+    }
+
+    /// Re-trace the outlines around the `changed` positions (chunk local, may lie outside of the chunk).
+    ///
+    /// Returns which of the previous outlines were kept. They stay in order at the front of `outlines`,
+    /// followed by the re-traced ones.
+    pub fn update(
+        &mut self,
+        terrain: &Terrain,
+        index: &Vector2I,
+        materials: &Materials,
+        options: &CollisionOptions,
+        changed: &[Vector2I],
+    ) -> Vec<bool> {
+        let sample = match chunk_sampler(terrain, index) {
+            Some(sample) => sample,
+            None => {
+                let kept = vec![false; self.outlines.len()];
+                *self = ChunkContours::default();
+                return kept;
+            }
+        };
+        let (min, max) = cell_range(Chunk::SIZE, options.mode);
+        let mut cells: Vec<Vector2I> = changed
+            .iter()
+            .flat_map(|local| cells_around(*local, options.mode))
+            .filter(|cell| cell.x >= min.x && cell.y >= min.y && cell.x <= max.x && cell.y <= max.y)
+            .collect();
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells.dedup();
+
+        // Replace the sides of the cells, outlines through any of their end points have to be re-traced
+        let mut removed: HashSet<(Vector2I, Vector2I)> = HashSet::new();
+        let mut added: Vec<Segment2I> = Vec::new();
+        let mut points: HashSet<Vector2I> = HashSet::new();
+        for cell in cells {
+            let old = [self.own.remove(&cell), self.foreign.remove(&cell)];
+            for side in old.iter().flatten().flatten() {
+                removed.insert((side.from, side.to));
+                points.extend([side.from, side.to]);
+            }
+            match cell_sides(&sample, Chunk::SIZE, materials, options.mode, cell) {
+                Some((owned, sides)) => {
+                    points.extend(sides.iter().flat_map(|side| [side.from, side.to]));
+                    if owned {
+                        added.extend(sides.iter().copied());
+                        self.own.insert(cell, sides);
+                    } else {
+                        self.foreign.insert(cell, sides);
+                    }
+                }
+                None => (),
+            }
+        }
+
+        // Simplified outlines may cut corners, so they also depend on the texels next to them
+        let margin = options.simplify_tolerance.map(|tolerance| tolerance + 1.0);
+        let near_change = |outline: &Outline| match margin {
+            Some(margin) => {
+                let (min, max) = bounds(&outline.points);
+                changed.iter().any(|local| {
+                    let center = Vector2F::from(*local) + Vector2F::ONE * 0.5;
+                    center.x >= min.x - margin
+                        && center.x <= max.x + margin
+                        && center.y >= min.y - margin
+                        && center.y <= max.y + margin
+                })
+            }
+            None => false,
+        };
+        let kept: Vec<bool> = self
+            .islands
+            .iter()
+            .zip(self.outlines.iter())
+            .map(|(island, outline)| {
+                !island
+                    .iter()
+                    .any(|side| points.contains(&side.from) || points.contains(&side.to))
+                    && !near_change(outline)
+            })
+            .collect();
+
+        let mut sides: Vec<Segment2I> = Vec::new();
+        let islands = std::mem::take(&mut self.islands);
+        let outlines = std::mem::take(&mut self.outlines);
+        for ((island, outline), keep) in islands.into_iter().zip(outlines).zip(kept.iter()) {
+            if *keep {
+                self.islands.push(island);
+                self.outlines.push(outline);
             } else {
-                0
+                sides.extend(
+                    island
+                        .into_iter()
+                        .filter(|side| !removed.contains(&(side.from, side.to))),
+                );
             }
-            | if local.x == 0 { 1 << 3 } else { 0 };
+        }
+        sides.extend(added);
+        // Sorted so that the outlines are traced the same way every run
+        sides.sort_by_key(|side| (side.from.y, side.from.x, side.to.y, side.to.x));
+        self.add_islands(assemble_islands(sides), &sample, materials, options);
+        kept
+    }
+}
+
+/// Outlines of a terrain chunk that continue across the borders of its loaded neighbours.
+///
+/// Sides between solid texels in different chunks are left out, and each side is meshed by a single chunk,
+/// so continuous ground forms open outlines that meet at the chunk edges. Unloaded chunks count as empty.
+pub fn calculate_terrain_collisions(
+    terrain: &Terrain,
+    index: &Vector2I,
+    materials: &Materials,
+    options: &CollisionOptions,
+) -> Vec<Outline> {
+    ChunkContours::new(terrain, index, materials, options).outlines
+}
+
+/// `calculate_terrain_collisions` of every chunk in `indices`, meshed in parallel
+pub fn calculate_terrain_collisions_par(
+    terrain: &Terrain,
+    indices: &[Vector2I],
+    materials: &Materials,
+    options: &CollisionOptions,
+) -> Vec<Vec<Outline>> {
+    indices
+        .par_iter()
+        .map(|index| calculate_terrain_collisions(terrain, index, materials, options))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{
+        calculate_area_collisions, calculate_collisions, calculate_terrain_collisions,
+        calculate_terrain_collisions_par, Boundary, ChunkContours, CollisionIsland,
+        CollisionOptions, MarchingMode, Outline, Winding,
+    };
+    use crate::{
+        mst::{chunk::Chunk, utils::texel_index_to_local, world_gen::gen_from_image},
+        resources::{Materials, Terrain},
+        util::{
+            polygon::{cross, remove_collinear, signed_area},
+            Vector2F, Vector2I,
+        },
+    };
+
+    fn case_chunk(case: usize) -> Chunk {
+        let mut chunk = Chunk::new();
+        let corners = [
+            Vector2I { x: 10, y: 10 },
+            Vector2I { x: 11, y: 10 },
+            Vector2I { x: 11, y: 11 },
+            Vector2I { x: 10, y: 11 },
+        ];
+        for (i, corner) in corners.iter().enumerate() {
+            if case & (1 << i) != 0 {
+                chunk.set_texel(corner, 1);
+            }
+        }
+        chunk
+    }
 
-        let mut sides: Vec<Segment2I>;
-        if chunk.texels[i].is_empty() {
-            sides = MST_CASE_MAP[chunk.texels[i].neighbour_mask as usize]
+    #[test]
+    fn interpolated_cases() {
+        let materials = Materials::default();
+        let blocky = CollisionOptions::default();
+        let interpolated = CollisionOptions {
+            mode: MarchingMode::Interpolated,
+            ..CollisionOptions::default()
+        };
+        // Every convex corner loses an eighth of a texel, every concave corner gains one
+        let expected: [(usize, f32); 16] = [
+            (0, 0.0),
+            (1, 0.5),
+            (1, 0.5),
+            (1, 1.5),
+            (1, 0.5),
+            (2, 1.0),
+            (1, 1.5),
+            (1, 2.5),
+            (1, 0.5),
+            (1, 1.5),
+            (2, 1.0),
+            (1, 2.5),
+            (1, 1.5),
+            (1, 2.5),
+            (1, 2.5),
+            (1, 3.5),
+        ];
+        for case in 0..16 {
+            let chunk = case_chunk(case);
+            let islands = calculate_collisions(&chunk, &materials, &interpolated);
+            let (island_count, area) = expected[case];
+            assert_eq!(islands.len(), island_count, "case {case:#06b}");
+
+            let total_area: f32 = islands
                 .iter()
-                .clone()
-                .map(|side| Segment2I {
-                    from: side.from + local,
-                    to: side.to + local,
-                })
-                .collect();
-        } else if !chunk.texels[i].is_empty() && edge_mask != 0 {
-            sides = Vec::with_capacity(Chunk::SIZE_X * 2 + Chunk::SIZE_Y * 2);
-            for i in 0..MST_EDGE_CASE_MAP.len() {
-                if edge_mask & (1 << i) != 0 {
-                    let edge = MST_EDGE_CASE_MAP[i];
-                    sides.push(Segment2I {
-                        from: edge.from + local,
-                        to: edge.to + local,
-                    })
+                .map(|island| signed_area(&island.points))
+                .sum();
+            assert!(
+                (total_area.abs() - area).abs() < 0.001,
+                "case {case:#06b}: area {total_area}"
+            );
+            // Same winding as the blocky outlines
+            let blocky_area: f32 = calculate_collisions(&chunk, &materials, &blocky)
+                .iter()
+                .map(|island| signed_area(&island.points))
+                .sum();
+            assert_eq!(total_area > 0.0, blocky_area > 0.0, "case {case:#06b}");
+        }
+    }
+
+    #[test]
+    fn blocky_diagonal_texels() {
+        let materials = Materials::default();
+        let mut chunk = Chunk::new();
+        for y in 0..Chunk::SIZE.y {
+            for x in 0..Chunk::SIZE.x {
+                if (x + y) % 2 == 0 {
+                    chunk.set_texel(&Vector2I { x, y }, 1);
                 }
             }
-        } else {
-            continue;
         }
+        // Texels that only touch at the corners get an outline each
+        let islands = calculate_collisions(&chunk, &materials, &CollisionOptions::default());
+        assert_eq!(islands.len(), (Chunk::SIZE.x * Chunk::SIZE.y / 2) as usize);
+        for island in islands {
+            assert_eq!(island.area, 1.0);
+        }
+    }
 
-        for side in sides {
-            // Check if the side can be attached to any island
-            // The naming of front and back are kind of misleading, and come from the VecDeque type.
-            // You can think of the front as the beginning of the island loop, and back the end.
+    #[test]
+    fn interpolated_chunk_edges() {
+        let materials = Materials::default();
+        let mut chunk = Chunk::new();
+        for y in 0..Chunk::SIZE.y {
+            for x in 0..Chunk::SIZE.x {
+                chunk.set_texel(&Vector2I { x, y }, 1);
+            }
+        }
+        let options = CollisionOptions {
+            mode: MarchingMode::Interpolated,
+            ..CollisionOptions::default()
+        };
+        // A full chunk is outlined along the chunk edges, only the corners are cut
+        let islands = calculate_collisions(&chunk, &materials, &options);
+        assert_eq!(islands.len(), 1);
+        assert_eq!(
+            islands[0].area,
+            (Chunk::SIZE.x * Chunk::SIZE.y) as f32 - 0.5
+        );
+    }
 
-            // Connect to an island if possible, otherwise create a new island
-            {
-                let mut connected_to: Option<&mut Island> = None;
-                for island in islands.iter_mut() {
-                    if island.back().is_some() && island.back().unwrap().to == side.from {
-                        connected_to = Some(island);
-                    }
+    #[test]
+    fn area_larger_than_chunk() {
+        let materials = Materials::default();
+        let size = Vector2I { x: 50, y: 3 };
+        // A bar along the middle row, with a texel missing in its middle
+        let sample = |local: Vector2I| match local.y == 1 && local.x > 0 && local.x != 25 {
+            true => Some(1),
+            false => Some(0),
+        };
+        let mut islands =
+            calculate_area_collisions(&sample, size, &materials, &CollisionOptions::default());
+        islands.sort_by(|a, b| a.min.x.total_cmp(&b.min.x));
+        assert_eq!(islands.len(), 2);
+        assert_eq!(islands[0].area, 24.0);
+        assert_eq!(islands[1].area, 24.0);
+        assert_eq!(islands[1].max, Vector2F { x: 50.0, y: 2.0 });
+        assert_eq!(islands[0].materials, vec![1]);
+    }
+
+    #[test]
+    fn across_chunk_borders() {
+        let materials = Materials::default();
+        let mut terrain = Terrain::from_chunks(HashMap::from([
+            (Vector2I::ZERO, Chunk::new()),
+            (Vector2I::RIGHT, Chunk::new()),
+        ]));
+        // Ground that continues into the right chunk
+        for y in 20..Chunk::SIZE.y {
+            for x in 0..Chunk::SIZE.x * 2 {
+                terrain.set_texel(&Vector2I { x, y }, 1);
+            }
+        }
+
+        let outlines = calculate_terrain_collisions(
+            &terrain,
+            &Vector2I::ZERO,
+            &materials,
+            &CollisionOptions::default(),
+        );
+        assert_eq!(outlines.len(), 1);
+        let outline = &outlines[0];
+        assert!(!outline.closed);
+        // No side along the border, the unloaded chunks around are empty
+        assert_eq!(
+            outline.points,
+            vec![
+                Vector2F { x: 32.0, y: 32.0 },
+                Vector2F { x: 0.0, y: 32.0 },
+                Vector2F { x: 0.0, y: 20.0 },
+                Vector2F { x: 32.0, y: 20.0 },
+            ]
+        );
+        assert_eq!(
+            outline.ghost_vertices,
+            Some((Vector2F { x: 33.0, y: 32.0 }, Vector2F { x: 33.0, y: 20.0 }))
+        );
+        assert_eq!(outline.material, 1);
+
+        for mode in [MarchingMode::Blocky, MarchingMode::Interpolated] {
+            let options = CollisionOptions {
+                mode,
+                ..CollisionOptions::default()
+            };
+            // The right chunk picks up where the left one ends
+            let left =
+                &calculate_terrain_collisions(&terrain, &Vector2I::ZERO, &materials, &options)[0];
+            let right =
+                &calculate_terrain_collisions(&terrain, &Vector2I::RIGHT, &materials, &options)[0];
+            let offset = Vector2F::RIGHT * Chunk::SIZE.x as f32;
+            assert!(!left.closed && !right.closed, "{mode:?}");
+            assert_eq!(
+                *left.points.last().unwrap(),
+                *right.points.first().unwrap() + offset
+            );
+            assert_eq!(
+                *right.points.last().unwrap() + offset,
+                *left.points.first().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let materials = Materials::default();
+        let terrain = Terrain::from_chunks(gen_from_image(
+            "./assets/terrain/complex_terrain.png",
+            &materials,
+        ));
+        let mut indices: Vec<Vector2I> = terrain.chunk_iter().map(|(index, _)| *index).collect();
+        indices.sort_by_key(|index| (index.y, index.x));
+        let options = CollisionOptions::default();
+        let parallel = calculate_terrain_collisions_par(&terrain, &indices, &materials, &options);
+        assert_eq!(parallel.len(), indices.len());
+        for (index, outlines) in indices.iter().zip(parallel) {
+            assert_eq!(
+                outlines,
+                calculate_terrain_collisions(&terrain, index, &materials, &options)
+            );
+        }
+    }
+
+    #[test]
+    fn island_metadata() {
+        let materials = Materials::default();
+        let (dirt, stone, water) = (1, 3, materials.find("water").unwrap());
+        let mut chunk = Chunk::new();
+        // A block of dirt with a stone texel and a flooded cave, and a texel of stone inside the cave
+        for y in 2..12 {
+            for x in 2..12 {
+                chunk.set_texel(&Vector2I { x, y }, dirt);
+            }
+        }
+        chunk.set_texel(&Vector2I { x: 3, y: 3 }, stone);
+        for y in 5..9 {
+            for x in 5..9 {
+                chunk.set_texel(&Vector2I { x, y }, water);
+            }
+        }
+        chunk.set_texel(&Vector2I { x: 6, y: 6 }, stone);
+
+        for mode in [MarchingMode::Blocky, MarchingMode::Interpolated] {
+            let options = CollisionOptions {
+                mode,
+                ..CollisionOptions::default()
+            };
+            let mut islands = calculate_collisions(&chunk, &materials, &options);
+            assert_eq!(islands.len(), 3, "{mode:?}");
+            islands.sort_by(|a, b| b.area.total_cmp(&a.area));
+            let (outer, cave, pebble) = (&islands[0], &islands[1], &islands[2]);
+
+            assert_eq!(outer.boundary, Boundary::Outer);
+            assert_eq!(outer.winding, Winding::Clockwise);
+            assert_eq!(outer.materials, vec![dirt, stone]);
+            assert_eq!(cave.boundary, Boundary::Hole);
+            assert_eq!(cave.winding, Winding::CounterClockwise);
+            assert_eq!(cave.materials, vec![water]);
+            assert_eq!(pebble.boundary, Boundary::Outer);
+            assert_eq!(pebble.materials, vec![stone]);
+
+            match mode {
+                MarchingMode::Blocky => {
+                    assert_eq!(outer.area, 100.0);
+                    assert_eq!(cave.area, 16.0);
+                    assert_eq!(outer.min, Vector2F { x: 2.0, y: 2.0 });
+                    assert_eq!(outer.max, Vector2F { x: 12.0, y: 12.0 });
+                }
+                MarchingMode::Interpolated => {
+                    assert!(outer.area < 100.0 && cave.area < 16.0);
+                    assert_eq!(outer.min, Vector2F { x: 2.0, y: 2.0 });
                 }
+            }
+        }
+    }
 
-                match connected_to {
-                    Some(back) => {
-                        back.push_back(side);
-                    }
-                    None => {
-                        let mut island: Island = Island::new();
-                        island.push_back(side);
-                        islands.push(island);
-                    }
+    fn random_chunk(rng: &mut StdRng, density: f64) -> Chunk {
+        // Water doesn't collide, and unknown ids do
+        let ids = [1, 3, 7, 200];
+        let mut chunk = Chunk::new();
+        for y in 0..Chunk::SIZE.y {
+            for x in 0..Chunk::SIZE.x {
+                if rng.gen_bool(density) {
+                    chunk.set_texel(&Vector2I { x, y }, ids[rng.gen_range(0..ids.len())]);
                 }
             }
+        }
+        chunk
+    }
 
-            // Find connected islands
-            loop {
-                let mut merge_index: Option<usize> = None;
-                'outer: for i in 0..islands.len() {
-                    for j in 0..islands.len() {
-                        if i == j {
-                            continue;
-                        }
-                        if islands[i].back().is_some()
-                            && islands[j].front().is_some()
-                            && islands[i].back().unwrap().to == islands[j].front().unwrap().from
-                        {
-                            merge_index = Some(i);
-                            break 'outer;
-                        }
-                    }
+    /// Loops around `point`, counted positive for outlines with a positive signed area
+    fn winding_number(islands: &[CollisionIsland], point: Vector2F) -> i32 {
+        let mut winding = 0;
+        for island in islands {
+            for side in island.points.windows(2) {
+                let (a, b) = (side[0], side[1]);
+                let turn = cross(a, b, point);
+                if a.y <= point.y && b.y > point.y && turn > 0.0 {
+                    winding += 1;
+                } else if a.y > point.y && b.y <= point.y && turn < 0.0 {
+                    winding -= 1;
                 }
+            }
+        }
+        winding
+    }
+
+    /// Whether the segments cross at a point inside of both
+    fn crosses(a: (Vector2F, Vector2F), b: (Vector2F, Vector2F)) -> bool {
+        let (d1, d2) = (cross(a.0, a.1, b.0), cross(a.0, a.1, b.1));
+        let (d3, d4) = (cross(b.0, b.1, a.0), cross(b.0, b.1, a.1));
+        d1 * d2 < 0.0 && d3 * d4 < 0.0
+    }
+
+    #[test]
+    fn random_chunks() {
+        let materials = Materials::default();
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for round in 0..12 {
+            let density = [0.1, 0.5, 0.9][round % 3];
+            let chunk = random_chunk(&mut rng, density);
+            for mode in [MarchingMode::Blocky, MarchingMode::Interpolated] {
+                let options = CollisionOptions {
+                    mode,
+                    ..CollisionOptions::default()
+                };
+                let islands = calculate_collisions(&chunk, &materials, &options);
+                let context = format!("round {round}, {mode:?}");
 
-                // Merge connected islands
-                match merge_index {
-                    Some(index) => {
-                        let mut merge_from = islands.swap_remove(index);
-                        match islands.iter_mut().find(|island| match island.front() {
-                            Some(front) => front.from == merge_from.back().unwrap().to,
-                            None => false,
-                        }) {
-                            Some(merge_to) => loop {
-                                match merge_from.pop_back() {
-                                    Some(segment) => merge_to.push_front(segment),
-                                    None => break,
-                                }
-                            },
-                            None => (),
-                        };
+                let mut sides: Vec<(Vector2F, Vector2F)> = Vec::new();
+                for island in islands.iter() {
+                    assert!(island.points.len() >= 4, "{context}");
+                    assert_eq!(island.points.first(), island.points.last(), "{context}");
+                    assert!(island.area > 0.0, "{context}");
+                    sides.extend(island.points.windows(2).map(|side| (side[0], side[1])));
+                }
+                // Outlines may touch at their corners, but never cross themselves or each other
+                for i in 0..sides.len() {
+                    for j in i + 1..sides.len() {
+                        assert!(
+                            !crosses(sides[i], sides[j]),
+                            "{context}: {:?} crosses {:?}",
+                            sides[i],
+                            sides[j]
+                        );
                     }
-                    None => break,
+                }
+
+                // Colliding texels are inside of exactly one more outer boundary than holes, others are not
+                for i in 0..chunk.texels.len() {
+                    let local = texel_index_to_local(i);
+                    let center = Vector2F::from(local) + Vector2F::ONE * 0.5;
+                    let expected = materials.collides(chunk.texels[i].id) as i32;
+                    assert_eq!(
+                        winding_number(&islands, center),
+                        expected,
+                        "{context}: texel {local:?}"
+                    );
                 }
             }
         }
     }
 
-    let mut result: Vec<Vec<Vector2F>> = Vec::with_capacity(islands.len());
-    for island in islands {
-        if island.len() < 4 {
-            continue;
-        }
-        let mut points: Vec<Vector2F> = Vec::with_capacity(island.len() + 1);
-        points.push(Vector2F::from(island.front().unwrap().from));
-        let mut current_angle: Option<f32> = None;
-        for side in island {
-            if current_angle.is_some() && (current_angle.unwrap() - side.angle()).abs() < 0.1 {
-                let len = points.len();
-                points[len - 1] = Vector2F::from(side.to)
-            } else {
-                current_angle = Some(side.angle());
-                points.push(Vector2F::from(side.to));
+    /// Outlines that only differ in where closed ones start look the same
+    fn normalized(outlines: &[Outline]) -> Vec<String> {
+        let mut result: Vec<String> = outlines
+            .iter()
+            .map(|outline| {
+                let mut points = match outline.closed {
+                    true => remove_collinear(&outline.points),
+                    false => outline.points.clone(),
+                };
+                if outline.closed {
+                    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+                }
+                format!(
+                    "{points:?} {:?} {}",
+                    outline.ghost_vertices, outline.material
+                )
+            })
+            .collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn update_matches_new() {
+        let materials = Materials::default();
+        let mut rng = StdRng::seed_from_u64(0xed17);
+        for mode in [MarchingMode::Blocky, MarchingMode::Interpolated] {
+            let options = CollisionOptions {
+                mode,
+                ..CollisionOptions::default()
+            };
+            let mut terrain = Terrain::from_chunks(HashMap::from_iter(
+                (-1..=1).map(|x| (Vector2I { x, y: 0 }, random_chunk(&mut rng, 0.5))),
+            ));
+            let mut contours = ChunkContours::new(&terrain, &Vector2I::ZERO, &materials, &options);
+            let mut kept_count = 0;
+            for round in 0..20 {
+                // Edits in and around the chunk, like a brush would make them
+                let center = Vector2I {
+                    x: rng.gen_range(-3..Chunk::SIZE.x + 3),
+                    y: rng.gen_range(0..Chunk::SIZE.y),
+                };
+                let id = [0, 1, 3][rng.gen_range(0..3)];
+                let mut changed: Vec<Vector2I> = Vec::new();
+                for y in -2..=2 {
+                    for x in -2..=2 {
+                        let local = center + Vector2I { x, y };
+                        if terrain.global_to_texel(&local).is_some() {
+                            terrain.set_texel(&local, id);
+                            changed.push(local);
+                        }
+                    }
+                }
+
+                let kept =
+                    contours.update(&terrain, &Vector2I::ZERO, &materials, &options, &changed);
+                kept_count += kept.iter().filter(|kept| **kept).count();
+                let expected = ChunkContours::new(&terrain, &Vector2I::ZERO, &materials, &options);
+                assert_eq!(
+                    normalized(&contours.outlines),
+                    normalized(&expected.outlines),
+                    "{mode:?}, round {round}"
+                );
             }
+            // Outlines away from the edits are kept
+            assert!(kept_count > 0, "{mode:?}");
         }
-        result.push(points);
     }
-    result
 }
@@ -0,0 +1,159 @@
+//! Binary terrain format.
+//!
+//! All values are little-endian:
+//! - magic `DNRT`, format version (u16), chunk size x and y (u16, u16), chunk count (u32)
+//! - per chunk: index x and y (i32, i32), run count (u32), runs of (length: u16, id: u8)
+//!
+//! Texel ids are run-length encoded row by row. Neighbour masks are not stored,
+//! they are rebuilt when the chunk is loaded.
+
+use std::{
+    collections::HashMap,
+    io::{self, Error, ErrorKind, Read, Write},
+};
+
+use super::{chunk::Chunk, texel::TexelID, utils::texel_index_to_local};
+use crate::util::Vector2I;
+
+pub const MAGIC: [u8; 4] = *b"DNRT";
+pub const VERSION: u16 = 1;
+
+pub fn write_chunks<'a, W: Write>(
+    writer: &mut W,
+    chunks: Vec<(&'a Vector2I, &'a Chunk)>,
+) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(Chunk::SIZE_X as u16).to_le_bytes())?;
+    writer.write_all(&(Chunk::SIZE_Y as u16).to_le_bytes())?;
+    writer.write_all(&(chunks.len() as u32).to_le_bytes())?;
+
+    for (index, chunk) in chunks {
+        writer.write_all(&index.x.to_le_bytes())?;
+        writer.write_all(&index.y.to_le_bytes())?;
+        let runs = encode_texels(chunk);
+        writer.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (length, id) in runs {
+            writer.write_all(&length.to_le_bytes())?;
+            writer.write_all(&[id])?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_chunks<R: Read>(reader: &mut R) -> io::Result<HashMap<Vector2I, Chunk>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Not a terrain file"));
+    }
+    let version = read_u16(reader)?;
+    if version != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported terrain format version {version}"),
+        ));
+    }
+    let size = (read_u16(reader)? as usize, read_u16(reader)? as usize);
+    if size != (Chunk::SIZE_X, Chunk::SIZE_Y) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Chunk size {size:?} doesn't match the current chunk size"),
+        ));
+    }
+
+    let chunk_count = read_u32(reader)?;
+    let mut chunk_map: HashMap<Vector2I, Chunk> = HashMap::with_capacity(chunk_count as usize);
+    for _ in 0..chunk_count {
+        let index = Vector2I {
+            x: read_i32(reader)?,
+            y: read_i32(reader)?,
+        };
+        let run_count = read_u32(reader)?;
+        let mut runs: Vec<(u16, TexelID)> = Vec::with_capacity(run_count as usize);
+        for _ in 0..run_count {
+            let length = read_u16(reader)?;
+            let mut id = [0u8; 1];
+            reader.read_exact(&mut id)?;
+            runs.push((length, id[0]));
+        }
+        chunk_map.insert(index, decode_texels(&runs)?);
+    }
+    Ok(chunk_map)
+}
+
+fn encode_texels(chunk: &Chunk) -> Vec<(u16, TexelID)> {
+    let mut runs: Vec<(u16, TexelID)> = Vec::new();
+    for texel in chunk.texels.iter() {
+        match runs.last_mut() {
+            Some((length, id)) if *id == texel.id => *length += 1,
+            _ => runs.push((1, texel.id)),
+        }
+    }
+    runs
+}
+
+fn decode_texels(runs: &[(u16, TexelID)]) -> io::Result<Chunk> {
+    let mut chunk = Chunk::new();
+    let mut i = 0;
+    for (length, id) in runs {
+        if i + *length as usize > chunk.texels.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Too many texels in chunk",
+            ));
+        }
+        for _ in 0..*length {
+            // Going through set_texel rebuilds the neighbour masks
+            chunk.set_texel(&texel_index_to_local(i), *id);
+            i += 1;
+        }
+    }
+    if i != chunk.texels.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Too few texels in chunk",
+        ));
+    }
+    Ok(chunk)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_texels, read_chunks};
+    use crate::{mst::chunk::Chunk, util::Vector2I};
+
+    #[test]
+    fn run_length() {
+        let mut chunk = Chunk::new();
+        chunk.set_texel(&Vector2I { x: 1, y: 0 }, 3);
+        chunk.set_texel(&Vector2I { x: 2, y: 0 }, 3);
+
+        let runs = encode_texels(&chunk);
+        assert_eq!(runs, vec![(1, 0), (2, 3), (1021, 0)]);
+    }
+
+    #[test]
+    fn invalid_magic() {
+        let data: Vec<u8> = b"PNG\0\x01\x00".to_vec();
+        assert!(read_chunks(&mut data.as_slice()).is_err());
+    }
+}
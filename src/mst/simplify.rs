@@ -0,0 +1,321 @@
+use crate::util::{polygon::segments_cross, Vector2F, Vector2I};
+
+/// Distance from `point` to the segment between `a` and `b`
+fn distance_to_segment(point: Vector2F, a: Vector2F, b: Vector2F) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    if length_squared == 0.0 {
+        return (point - a).length();
+    }
+    let ap = point - a;
+    let t = ((ap.x * ab.x + ap.y * ab.y) / length_squared).clamp(0.0, 1.0);
+    (point - (a + ab * t)).length()
+}
+
+/// Whether the segment cuts into a solid texel deeper than its inscribed circle.
+///
+/// Segments running along texel faces or across corners don't count.
+fn crosses_solid(a: Vector2F, b: Vector2F, is_solid: &dyn Fn(Vector2I) -> bool) -> bool {
+    let steps = ((b - a).length() / 0.25).ceil().max(1.0) as i32;
+    for step in 0..=steps {
+        let p = a + (b - a) * (step as f32 / steps as f32);
+        let cell = Vector2I {
+            x: p.x.floor() as i32,
+            y: p.y.floor() as i32,
+        };
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let texel = cell + Vector2I { x, y };
+                let center = Vector2F::from(texel) + Vector2F::ONE * 0.5;
+                if is_solid(texel) && distance_to_segment(center, a, b) < 0.5 - 1e-4 {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Point between `first` and `last` farthest from the segment between them, and its distance
+fn farthest_point(points: &[Vector2F], first: usize, last: usize) -> (usize, f32) {
+    let (mut farthest, mut max_distance) = ((first + last) / 2, 0.0);
+    for i in first + 1..last {
+        let distance = distance_to_segment(points[i], points[first], points[last]);
+        if distance > max_distance {
+            farthest = i;
+            max_distance = distance;
+        }
+    }
+    (farthest, max_distance)
+}
+
+fn simplify_range(
+    points: &[Vector2F],
+    first: usize,
+    last: usize,
+    tolerance: f32,
+    is_solid: &dyn Fn(Vector2I) -> bool,
+    keep: &mut Vec<bool>,
+) {
+    if last <= first + 1 {
+        return;
+    }
+    let (farthest, max_distance) = farthest_point(points, first, last);
+    if max_distance <= tolerance && !crosses_solid(points[first], points[last], is_solid) {
+        return;
+    }
+    keep[farthest] = true;
+    simplify_range(points, first, farthest, tolerance, is_solid, keep);
+    simplify_range(points, farthest, last, tolerance, is_solid, keep);
+}
+
+/// Split the shortcuts between kept points that cross the rest of the simplified outline,
+/// or another outline according to `crosses_other`, until none do.
+///
+/// Keeping every point gives back the original outline, which doesn't cross anything.
+fn untangle(
+    points: &[Vector2F],
+    crosses_other: &dyn Fn(Vector2F, Vector2F) -> bool,
+    keep: &mut [bool],
+) {
+    loop {
+        let kept: Vec<usize> = (0..points.len()).filter(|i| keep[*i]).collect();
+        let sides: Vec<(Vector2F, Vector2F)> = kept
+            .windows(2)
+            .map(|side| (points[side[0]], points[side[1]]))
+            .collect();
+        let mut split = false;
+        for (i, side) in kept.windows(2).enumerate() {
+            let (first, last) = (side[0], side[1]);
+            if last <= first + 1 {
+                continue;
+            }
+            let crosses_self = sides
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && segments_cross(sides[i], *other));
+            if crosses_self || crosses_other(sides[i].0, sides[i].1) {
+                keep[farthest_point(points, first, last).0] = true;
+                split = true;
+            }
+        }
+        if !split {
+            return;
+        }
+    }
+}
+
+/// Douglas-Peucker simplification of a closed outline in texel space.
+///
+/// Removed points are at most `tolerance` away from the simplified outline, and the
+/// simplified outline never cuts through a solid texel, crosses itself or another outline
+/// according to `crosses_other`. A repeated closing point is preserved.
+pub fn simplify_loop(
+    points: &[Vector2F],
+    tolerance: f32,
+    is_solid: &dyn Fn(Vector2I) -> bool,
+    crosses_other: &dyn Fn(Vector2F, Vector2F) -> bool,
+) -> Vec<Vector2F> {
+    let closed = points.len() > 1 && points.first() == points.last();
+    let open_len = if closed {
+        points.len() - 1
+    } else {
+        points.len()
+    };
+    if open_len < 4 {
+        return points.to_vec();
+    }
+
+    // Split the loop at the first point and the point farthest from it
+    let start = points[0];
+    let (split, _) = points[..open_len]
+        .iter()
+        .enumerate()
+        .map(|(i, point)| (i, (*point - start).length_squared()))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+
+    let mut extended: Vec<Vector2F> = points[..open_len].to_vec();
+    extended.push(start);
+    let mut keep = vec![false; extended.len()];
+    keep[0] = true;
+    keep[split] = true;
+    simplify_range(&extended, 0, split, tolerance, is_solid, &mut keep);
+    simplify_range(&extended, split, open_len, tolerance, is_solid, &mut keep);
+    keep[open_len] = true;
+    untangle(&extended, crosses_other, &mut keep);
+
+    let mut result: Vec<Vector2F> = (0..open_len)
+        .filter(|i| keep[*i])
+        .map(|i| extended[i])
+        .collect();
+    if result.len() < 3 {
+        return points.to_vec();
+    }
+    if closed {
+        result.push(start);
+    }
+    result
+}
+
+/// Douglas-Peucker simplification of an open chain in texel space, keeping both end points.
+/// Same guarantees as `simplify_loop`.
+pub fn simplify_chain(
+    points: &[Vector2F],
+    tolerance: f32,
+    is_solid: &dyn Fn(Vector2I) -> bool,
+    crosses_other: &dyn Fn(Vector2F, Vector2F) -> bool,
+) -> Vec<Vector2F> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let last = points.len() - 1;
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[last] = true;
+    simplify_range(points, 0, last, tolerance, is_solid, &mut keep);
+    untangle(points, crosses_other, &mut keep);
+    (0..points.len())
+        .filter(|i| keep[*i])
+        .map(|i| points[i])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simplify_loop;
+    use crate::{
+        mst::{
+            chunk::Chunk,
+            marching_square::{calculate_collisions, CollisionOptions},
+            world_gen::gen_from_image,
+        },
+        resources::Materials,
+        util::{polygon::segments_cross, Vector2F, Vector2I},
+    };
+
+    #[test]
+    fn staircase() {
+        // Solid texels below the diagonal of a 8x8 square
+        let is_solid =
+            |texel: Vector2I| texel.x >= 0 && texel.y < 8 && texel.x <= texel.y && texel.y >= 0;
+        let mut points = vec![Vector2F { x: 0.0, y: 8.0 }, Vector2F { x: 0.0, y: 0.0 }];
+        for i in 0..8 {
+            points.push(Vector2F {
+                x: i as f32 + 1.0,
+                y: i as f32,
+            });
+            points.push(Vector2F {
+                x: i as f32 + 1.0,
+                y: i as f32 + 1.0,
+            });
+        }
+        points.push(Vector2F { x: 0.0, y: 8.0 });
+
+        let simplified = simplify_loop(&points, 1.0, &is_solid, &|_, _| false);
+        // The stairs become a single diagonal that only crosses empty texels
+        assert_eq!(simplified.len(), 6);
+        assert_eq!(simplified.first(), simplified.last());
+        // Without room for error nothing changes
+        assert_eq!(
+            simplify_loop(&points, 0.0, &is_solid, &|_, _| false).len(),
+            points.len()
+        );
+    }
+
+    #[test]
+    fn thin_outlines_close_together() {
+        let materials = Materials::default();
+        // A thin diagonal line of texels, and a single texel that touches its shortcut's inscribed circle
+        let mut chunk = Chunk::new();
+        for (x, y) in [
+            (5, 2),
+            (4, 3),
+            (5, 3),
+            (4, 4),
+            (5, 4),
+            (3, 5),
+            (4, 5),
+            (2, 6),
+            (3, 6),
+            (2, 7),
+            (2, 4),
+        ] {
+            chunk.set_texel(&Vector2I { x, y }, 1);
+        }
+        let options = CollisionOptions {
+            simplify_tolerance: Some(1.0),
+            ..CollisionOptions::default()
+        };
+        let islands = calculate_collisions(&chunk, &materials, &options);
+        assert_eq!(islands.len(), 2);
+        let sides: Vec<(Vector2F, Vector2F)> = islands
+            .iter()
+            .flat_map(|island| island.points.windows(2).map(|side| (side[0], side[1])))
+            .collect();
+        for i in 0..sides.len() {
+            for j in i + 1..sides.len() {
+                assert!(
+                    !segments_cross(sides[i], sides[j]),
+                    "{:?} crosses {:?}",
+                    sides[i],
+                    sides[j]
+                );
+            }
+        }
+        // The line is still simplified where it's clear of the texel
+        let exact = calculate_collisions(&chunk, &materials, &CollisionOptions::default());
+        assert!(
+            islands
+                .iter()
+                .map(|island| island.points.len())
+                .sum::<usize>()
+                < exact
+                    .iter()
+                    .map(|island| island.points.len())
+                    .sum::<usize>()
+        );
+    }
+
+    fn vertex_count(path: &str, materials: &Materials, options: &CollisionOptions) -> usize {
+        gen_from_image(path, materials)
+            .values()
+            .map(|chunk| {
+                calculate_collisions(chunk, materials, options)
+                    .iter()
+                    .map(|island| island.points.len())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn sample_images() {
+        let materials = Materials::default();
+        let exact = CollisionOptions::default();
+        let simplified = CollisionOptions {
+            simplify_tolerance: Some(1.0),
+            ..CollisionOptions::default()
+        };
+        for (path, min_reduction) in [
+            ("./assets/terrain/complex_terrain.png", 0.12),
+            ("./assets/terrain/noise.png", 0.1),
+            ("./assets/terrain/terrain.png", 0.15),
+            ("./assets/terrain/vertical.png", 0.15),
+            ("./assets/terrain/chunk_1.png", 0.15),
+            // Axis aligned shapes have nothing to simplify
+            ("./assets/terrain/simple_terrain.png", 0.0),
+            ("./assets/terrain/chunk_0.png", 0.0),
+            ("./assets/terrain/solid_4x4.png", 0.0),
+        ] {
+            let before = vertex_count(path, &materials, &exact);
+            let after = vertex_count(path, &materials, &simplified);
+            assert!(after <= before);
+            assert!(
+                after as f32 <= before as f32 * (1.0 - min_reduction),
+                "{path}: expected at least {}% fewer vertices, {before} -> {after}",
+                min_reduction * 100.0
+            );
+        }
+    }
+}
@@ -1,14 +1,20 @@
 use super::{
     chunk::Chunk,
     texel::{Texel, TexelID},
-    utils::{global_to_index, global_to_local},
+    utils::{global_to_index, global_to_local, index_to_global},
+};
+use crate::{
+    resources::Materials,
+    util::{
+        noise::{fractal_noise_1d, fractal_noise_2d},
+        Vector2I,
+    },
 };
-use crate::util::Vector2I;
 use sdl2::{pixels::Color, surface::Surface};
 use std::collections::HashMap;
 
 // const DATA_PATH: &str = "./assets/terrain/noise.png";
-const DATA_PATH: &str = "./assets/terrain/complex_terrain.png";
+pub const DATA_PATH: &str = "./assets/terrain/complex_terrain.png";
 // const DATA_PATH: &str = "./assets/terrain/terrain.png";
 // const DATA_PATH: &str = "./assets/terrain/simple_terrain.png";
 // const DATA_PATH: &str = "./assets/terrain/chunk_0.png";
@@ -37,21 +43,29 @@ fn map_closest_color(color: Color, map: &HashMap<Color, TexelID>) -> TexelID {
     map[closest]
 }
 
-pub fn gen_from_image() -> HashMap<Vector2I, Chunk> {
+/// Map the render and image colours of every material to its id
+fn material_color_map(materials: &Materials) -> HashMap<Color, TexelID> {
+    let mut color_map: HashMap<Color, TexelID> = HashMap::new();
+    for (id, material) in materials.iter() {
+        for (r, g, b, _) in material.colors.iter() {
+            color_map.insert(Color::RGB(*r, *g, *b), id);
+        }
+        for (r, g, b) in material.image_colors.iter() {
+            color_map.insert(Color::RGB(*r, *g, *b), id);
+        }
+    }
+    if color_map.is_empty() {
+        color_map.insert(Color::RGB(0, 0, 0), Texel::EMPTY);
+    }
+    color_map
+}
+
+pub fn gen_from_image(path: &str, materials: &Materials) -> HashMap<Vector2I, Chunk> {
     let mut chunk_map: HashMap<Vector2I, Chunk> = HashMap::new();
 
-    let color_map: HashMap<Color, TexelID> = [
-        (Color::RGB(172, 191, 250), Texel::EMPTY),
-        (Color::RGB(30, 30, 30), Texel::EMPTY),
-        (Color::RGB(0, 0, 0), Texel::EMPTY),
-        (Color::RGB(158, 127, 99), 1),
-        (Color::RGB(70, 142, 71), 2),
-    ]
-    .iter()
-    .cloned()
-    .collect();
+    let color_map = material_color_map(materials);
 
-    let tex_surface = read_image(DATA_PATH);
+    let tex_surface = read_image(path);
 
     tex_surface.with_lock(|p_data| {
         let mut p_iter = p_data.iter();
@@ -79,3 +93,198 @@ pub fn gen_from_image() -> HashMap<Vector2I, Chunk> {
 
     chunk_map
 }
+
+/// Seeded procedural terrain generator.
+///
+/// The generated texels only depend on the settings, so the same seed always produces the same world.
+#[derive(Clone, Copy, Debug)]
+pub struct WorldGenerator {
+    pub seed: u64,
+    /// Size of the generated world in chunks, starting from index (0, 0)
+    pub size: Vector2I,
+    /// Average surface height in texels
+    pub surface_level: i32,
+    /// Maximum distance of the surface from the average height in texels
+    pub surface_amplitude: f32,
+    /// Horizontal scale of the hills in texels, larger is smoother
+    pub surface_scale: f32,
+    /// Cave noise values above this are carved out (0.0 - 1.0)
+    pub cave_threshold: f32,
+    /// Scale of the caves in texels
+    pub cave_scale: f32,
+    /// Ore noise values above this turn stone into ore (0.0 - 1.0)
+    pub ore_threshold: f32,
+    /// Scale of the ore pockets in texels
+    pub ore_scale: f32,
+    /// Depth below the surface in texels where the deep ore replaces the shallow one
+    pub deep_ore_depth: i32,
+    pub dirt: TexelID,
+    pub grass: TexelID,
+    pub stone: TexelID,
+    pub shallow_ore: TexelID,
+    pub deep_ore: TexelID,
+}
+
+impl WorldGenerator {
+    /// Names of the materials of each layer: dirt, grass, stone, shallow and deep ore
+    pub const MATERIAL_NAMES: [&'static str; 5] = ["dirt", "grass", "stone", "copper", "gold"];
+
+    // Salts so that each layer samples a different noise field from the same seed
+    const SURFACE_SALT: u64 = 0x5EED_0001;
+    const DIRT_SALT: u64 = 0x5EED_0002;
+    const CAVE_SALT: u64 = 0x5EED_0003;
+    const ORE_SALT: u64 = 0x5EED_0004;
+
+    /// Layer materials are looked up by name in `materials`, see `MATERIAL_NAMES`
+    pub fn new(seed: u64, materials: &Materials) -> WorldGenerator {
+        let [dirt, grass, stone, shallow_ore, deep_ore] =
+            Self::MATERIAL_NAMES.map(|name| match materials.find(name) {
+                Some(id) => id,
+                None => panic!("World generator material {name} is missing"),
+            });
+        WorldGenerator {
+            seed,
+            size: Vector2I { x: 8, y: 8 },
+            surface_level: 64,
+            surface_amplitude: 32.0,
+            surface_scale: 96.0,
+            cave_threshold: 0.62,
+            cave_scale: 24.0,
+            ore_threshold: 0.72,
+            ore_scale: 8.0,
+            deep_ore_depth: 96,
+            dirt,
+            grass,
+            stone,
+            shallow_ore,
+            deep_ore,
+        }
+    }
+
+    /// Surface height in texels at the given global x coordinate
+    pub fn surface_height(&self, x: i32) -> i32 {
+        let noise = fractal_noise_1d(
+            self.seed ^ Self::SURFACE_SALT,
+            x as f32 / self.surface_scale,
+            4,
+        );
+        self.surface_level + ((noise * 2.0 - 1.0) * self.surface_amplitude).round() as i32
+    }
+
+    pub fn texel_at(&self, global: &Vector2I) -> TexelID {
+        let depth = global.y - self.surface_height(global.x);
+        if depth < 0 {
+            return Texel::EMPTY;
+        }
+
+        let (x, y) = (global.x as f32, global.y as f32);
+
+        // Caves fade in below the surface so that the topsoil doesn't get riddled with holes
+        let cave = fractal_noise_2d(
+            self.seed ^ Self::CAVE_SALT,
+            x / self.cave_scale,
+            y / self.cave_scale,
+            3,
+        ) * (depth as f32 / 16.0).min(1.0);
+        if cave > self.cave_threshold {
+            return Texel::EMPTY;
+        }
+
+        let dirt_depth =
+            4 + (fractal_noise_1d(self.seed ^ Self::DIRT_SALT, x / 16.0, 2) * 8.0) as i32;
+        if depth == 0 {
+            self.grass
+        } else if depth < dirt_depth {
+            self.dirt
+        } else if fractal_noise_2d(
+            self.seed ^ Self::ORE_SALT,
+            x / self.ore_scale,
+            y / self.ore_scale,
+            2,
+        ) > self.ore_threshold
+        {
+            if depth < self.deep_ore_depth {
+                self.shallow_ore
+            } else {
+                self.deep_ore
+            }
+        } else {
+            self.stone
+        }
+    }
+
+    pub fn gen_chunk(&self, index: &Vector2I) -> Chunk {
+        let mut chunk = Chunk::new();
+        let origin = index_to_global(index);
+        for y in 0..Chunk::SIZE.y {
+            for x in 0..Chunk::SIZE.x {
+                let local = Vector2I { x, y };
+                chunk.set_texel(&local, self.texel_at(&(origin + local)));
+            }
+        }
+        chunk
+    }
+
+    pub fn gen_chunks(&self) -> HashMap<Vector2I, Chunk> {
+        let mut chunk_map: HashMap<Vector2I, Chunk> = HashMap::new();
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let index = Vector2I { x, y };
+                chunk_map.insert(index, self.gen_chunk(&index));
+            }
+        }
+        chunk_map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorldGenerator;
+    use crate::{
+        mst::{chunk::Chunk, texel::Texel},
+        resources::Materials,
+        util::Vector2I,
+    };
+
+    fn texel_ids(chunk: &Chunk) -> Vec<u8> {
+        chunk.texels.iter().map(|texel| texel.id).collect()
+    }
+
+    #[test]
+    fn deterministic() {
+        let materials = Materials::default();
+        let mut generator = WorldGenerator::new(1234, &materials);
+        generator.size = Vector2I { x: 3, y: 4 };
+        let a = generator.gen_chunks();
+        let b = generator.gen_chunks();
+
+        assert_eq!(a.len(), 12);
+        for (index, chunk) in a.iter() {
+            assert_eq!(texel_ids(chunk), texel_ids(&b[index]));
+        }
+
+        let other = WorldGenerator::new(4321, &materials).gen_chunk(&Vector2I { x: 1, y: 2 });
+        assert_ne!(texel_ids(&a[&Vector2I { x: 1, y: 2 }]), texel_ids(&other));
+    }
+
+    #[test]
+    fn surface_layers() {
+        let materials = Materials::default();
+        let generator = WorldGenerator::new(42, &materials);
+        for x in -64..64 {
+            let surface = generator.surface_height(x);
+            assert_eq!(
+                generator.texel_at(&Vector2I { x, y: surface - 1 }),
+                Texel::EMPTY
+            );
+            assert_eq!(
+                generator.texel_at(&Vector2I { x, y: surface }),
+                materials.find("grass").unwrap()
+            );
+            assert_eq!(
+                generator.texel_at(&Vector2I { x, y: surface + 1 }),
+                materials.find("dirt").unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Recorded input for replaying a session frame by frame.
+//!
+//! Replaying a recording against a world that starts out the same repeats the same
+//! terrain edits and physics steps, and gives the same `checksum`.
+//!
+//! Binary format, all values are little-endian:
+//! - magic `DNRI`, format version (u16), frame count (u32)
+//! - per frame: delta time in nanoseconds (u64),
+//!   mouse buttons (u8), position, velocity and scroll (i32 pairs),
+//!   keyboard bits (8 x u64), gamepad buttons (u32) and axes (6 x f32)
+
+use std::{
+    cell::RefCell,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use box2d_rs::b2_world::B2world;
+
+use crate::{
+    gl::renderer::{self, UnsafeCanvas},
+    resources::{
+        Action, GamepadState, Input, InputState, KeyboardState, MouseState, Terrain, UnsafeBox2D,
+    },
+    simulation::Simulation,
+    util::{arg_value, Vector2I},
+};
+
+pub const MAGIC: [u8; 4] = *b"DNRI";
+pub const VERSION: u16 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RecordedFrame {
+    pub delta_time: Duration,
+    pub input: InputState,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn new() -> Recording {
+        Recording { frames: Vec::new() }
+    }
+
+    pub fn push(&mut self, delta_time: Duration, input: InputState) {
+        self.frames.push(RecordedFrame { delta_time, input });
+    }
+
+    pub fn load(path: &Path) -> io::Result<Recording> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            self.write(&mut writer)?;
+            writer.flush()?;
+        }
+        fs::rename(&temp_path, path)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+
+        for frame in self.frames.iter() {
+            writer.write_all(&(frame.delta_time.as_nanos() as u64).to_le_bytes())?;
+            let mouse = &frame.input.mouse;
+            writer.write_all(&[mouse.buttons])?;
+            for vector in [mouse.position, mouse.velocity, mouse.scroll] {
+                writer.write_all(&vector.x.to_le_bytes())?;
+                writer.write_all(&vector.y.to_le_bytes())?;
+            }
+            for bits in frame.input.keyboard.bits() {
+                writer.write_all(&bits.to_le_bytes())?;
+            }
+            let (buttons, axes) = frame.input.gamepad.raw();
+            writer.write_all(&buttons.to_le_bytes())?;
+            for axis in axes {
+                writer.write_all(&axis.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Recording> {
+        if read_bytes::<4, R>(reader)? != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not an input recording"));
+        }
+        let version = u16::from_le_bytes(read_bytes(reader)?);
+        if version != VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported recording format version {version}"),
+            ));
+        }
+
+        let frame_count = u32::from_le_bytes(read_bytes(reader)?);
+        let mut recording = Recording::new();
+        for _ in 0..frame_count {
+            let delta_time = Duration::from_nanos(u64::from_le_bytes(read_bytes(reader)?));
+            let [buttons] = read_bytes(reader)?;
+            let mut vectors = [Vector2I::ZERO; 3];
+            for vector in vectors.iter_mut() {
+                vector.x = i32::from_le_bytes(read_bytes(reader)?);
+                vector.y = i32::from_le_bytes(read_bytes(reader)?);
+            }
+            let mut keys = [0u64; 8];
+            for bits in keys.iter_mut() {
+                *bits = u64::from_le_bytes(read_bytes(reader)?);
+            }
+            let gamepad_buttons = u32::from_le_bytes(read_bytes(reader)?);
+            let mut axes = [0f32; 6];
+            for axis in axes.iter_mut() {
+                *axis = f32::from_le_bytes(read_bytes(reader)?);
+            }
+
+            recording.push(
+                delta_time,
+                InputState {
+                    mouse: MouseState {
+                        buttons,
+                        position: vectors[0],
+                        velocity: vectors[1],
+                        scroll: vectors[2],
+                    },
+                    keyboard: KeyboardState::from_bits(keys),
+                    gamepad: GamepadState::from_raw(gamepad_buttons, axes),
+                },
+            );
+        }
+        Ok(recording)
+    }
+}
+
+/// Step the simulation through every recorded frame, and return the checksum afterwards
+pub fn replay(simulation: &mut Simulation, recording: &Recording) -> u64 {
+    for frame in recording.frames.iter() {
+        simulation.step_with_delta(frame.input, frame.delta_time);
+    }
+    checksum(simulation)
+}
+
+/// FNV-1a hash of the loaded terrain texels and the position, angle and velocity of every physics body
+pub fn checksum(simulation: &Simulation) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+
+    let terrain = simulation.world.fetch::<Terrain>();
+    let mut chunks: Vec<_> = terrain.chunk_iter().collect();
+    chunks.sort_by_key(|(index, _)| (index.y, index.x));
+    for (index, chunk) in chunks {
+        write(&index.x.to_le_bytes());
+        write(&index.y.to_le_bytes());
+        for texel in chunk.texels.iter() {
+            write(&[texel.id]);
+        }
+    }
+
+    let box2d = simulation.world.fetch::<UnsafeBox2D>();
+    for body_ptr in B2world::get_body_list(&RefCell::borrow(&box2d.world_ptr)).iter() {
+        let body = RefCell::borrow(&body_ptr);
+        let position = body.get_position();
+        let velocity = body.get_linear_velocity();
+        for value in [
+            position.x,
+            position.y,
+            body.get_angle(),
+            velocity.x,
+            velocity.y,
+        ] {
+            write(&value.to_le_bytes());
+        }
+    }
+    hash
+}
+
+/// Recording, replaying and saving around a run of the game, set up from the command line arguments:
+/// - `--record <path>` saves the input of every frame when finishing
+/// - `--replay <path>` plays back a recording instead of the input, the other arguments have to match the recorded run
+/// - `--frames <count>` ends the session after the given number of frames
+/// - `--screenshot <path>` saves the last frame as a PNG image when finishing
+/// - `--save <path>` saves the terrain when finishing
+pub struct Session {
+    record_path: Option<PathBuf>,
+    recording: Recording,
+    replay: Option<Recording>,
+    frame_limit: Option<u64>,
+    screenshot_path: Option<PathBuf>,
+    save_path: Option<PathBuf>,
+}
+
+impl Session {
+    pub fn from_args(args: &[String]) -> Session {
+        let replay: Option<Recording> =
+            arg_value(args, "--replay").map(|path| match Recording::load(Path::new(path)) {
+                Ok(recording) => recording,
+                Err(error) => panic!("Failed to load recording from {path}: {error:?}"),
+            });
+        Session {
+            record_path: arg_value(args, "--record").map(PathBuf::from),
+            recording: Recording::new(),
+            replay,
+            frame_limit: arg_value(args, "--frames").map(|frames| {
+                frames
+                    .parse()
+                    .expect("Frame count must be a positive integer")
+            }),
+            screenshot_path: arg_value(args, "--screenshot").map(PathBuf::from),
+            save_path: arg_value(args, "--save").map(PathBuf::from),
+        }
+    }
+
+    /// Step the simulation with the input of a frame, or the recorded one when replaying.
+    ///
+    /// Returns false once the session is over.
+    pub fn step(
+        &mut self,
+        simulation: &mut Simulation,
+        input: InputState,
+        delta_time: Duration,
+    ) -> bool {
+        let (input, delta_time) = match &self.replay {
+            Some(replay) => match replay.frames.get(self.recording.frames.len()) {
+                Some(frame) => (frame.input, frame.delta_time),
+                None => return false,
+            },
+            None => (input, delta_time),
+        };
+        self.recording.push(delta_time, input);
+
+        simulation.step_with_delta(input, delta_time);
+        if simulation
+            .world
+            .fetch::<Input>()
+            .action_pressed(Action::Quit)
+        {
+            return false;
+        }
+        match self.frame_limit {
+            Some(limit) => simulation.frame() < limit,
+            None => true,
+        }
+    }
+
+    /// Print the checksum and save whatever the arguments asked for
+    pub fn finish(self, simulation: &Simulation) {
+        if self.record_path.is_some() || self.replay.is_some() {
+            println!(
+                "Checksum after {} frames: {:016x}",
+                simulation.frame(),
+                checksum(simulation)
+            );
+        }
+
+        match self.record_path {
+            Some(path) => match self.recording.save(&path) {
+                Ok(_) => println!("Saved recording to {}", path.display()),
+                Err(error) => println!("Failed to save recording: {error:?}"),
+            },
+            None => (),
+        }
+
+        match self.screenshot_path {
+            Some(path) => match simulation.world.try_fetch::<UnsafeCanvas>() {
+                Some(canvas) => match renderer::save_png(&canvas, &path) {
+                    Ok(_) => println!("Saved screenshot to {}", path.display()),
+                    Err(error) => println!("Failed to save screenshot: {error:?}"),
+                },
+                None => println!("Failed to save screenshot: nothing is rendered"),
+            },
+            None => (),
+        }
+
+        match self.save_path {
+            Some(path) => {
+                let terrain = simulation.world.fetch::<Terrain>();
+                match terrain.save(&path) {
+                    Ok(_) => println!("Saved terrain to {}", path.display()),
+                    Err(error) => println!("Failed to save terrain: {error:?}"),
+                }
+            }
+            None => (),
+        }
+    }
+}
+
+fn read_bytes<const N: usize, R: Read>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::{checksum, replay, Recording, Session};
+    use crate::{
+        mst::chunk::Chunk,
+        resources::{InputState, Materials, MouseButton, Terrain},
+        simulation::{Simulation, SimulationOptions},
+        util::{TempPath, Vector2I},
+    };
+
+    fn simulation() -> Simulation {
+        let terrain = Terrain::from_chunks(HashMap::from([
+            (Vector2I::ZERO, Chunk::new()),
+            (Vector2I::RIGHT, Chunk::new()),
+        ]));
+        Simulation::new(terrain, Materials::default(), SimulationOptions::default())
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let mut live = simulation();
+        let mut recording = Recording::new();
+        let mut input = InputState::default();
+        for frame in 0..60 {
+            // Paint while dragging the mouse across both chunks
+            input.mouse.position = Vector2I {
+                x: frame * 4,
+                y: 40,
+            };
+            input.mouse.set_button_state(&MouseButton::Left, frame < 40);
+            let delta_time = Duration::from_millis(10 + frame as u64 % 3 * 5);
+            recording.push(delta_time, input);
+            live.step_with_delta(input, delta_time);
+        }
+
+        let mut bytes: Vec<u8> = Vec::new();
+        recording.write(&mut bytes).unwrap();
+        let loaded = Recording::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.frames.len(), 60);
+
+        let mut replayed = simulation();
+        assert_ne!(checksum(&replayed), checksum(&live));
+        assert_eq!(replay(&mut replayed, &loaded), checksum(&live));
+    }
+
+    #[test]
+    fn invalid_magic() {
+        let data: Vec<u8> = b"DNRT\x01\x00".to_vec();
+        assert!(Recording::read(&mut data.as_slice()).is_err());
+    }
+
+    #[test]
+    fn session_records_and_replays() {
+        let path = TempPath::new("session.dnri");
+        let path_arg = path.as_ref().to_string_lossy().to_string();
+        let args = |flag: &str| -> Vec<String> {
+            [flag, path_arg.as_str(), "--frames", "30"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect()
+        };
+
+        let mut live = simulation();
+        let mut session = Session::from_args(&args("--record"));
+        let mut input = InputState::default();
+        let mut frames = 0;
+        loop {
+            input.mouse.position = Vector2I {
+                x: frames * 8,
+                y: 20,
+            };
+            input.mouse.set_button_state(&MouseButton::Left, true);
+            frames += 1;
+            if !session.step(&mut live, input, Duration::from_millis(16)) {
+                break;
+            }
+        }
+        // The frame limit ends the session
+        assert_eq!(frames, 30);
+        session.finish(&live);
+
+        // Live input is ignored while replaying
+        let mut replayed = simulation();
+        let mut session = Session::from_args(&args("--replay"));
+        while session.step(&mut replayed, InputState::default(), Duration::ZERO) {}
+        assert_eq!(replayed.frame(), 30);
+        assert_eq!(checksum(&replayed), checksum(&live));
+    }
+}
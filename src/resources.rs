@@ -1,11 +1,17 @@
 mod box2d_world;
 mod camera;
+mod materials;
 mod terrain;
 mod time;
 mod input;
+mod bindings;
+mod edit_history;
 
+pub use bindings::*;
 pub use box2d_world::*;
 pub use camera::*;
+pub use edit_history::*;
+pub use input::*;
+pub use materials::*;
 pub use terrain::*;
 pub use time::*;
-pub use input::*;
@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Error, ErrorKind},
+    path::Path,
+    str::FromStr,
+};
+
+use sdl2::{
+    controller::{Axis, Button},
+    keyboard::Scancode,
+};
+
+use super::MouseButton;
+
+/// Something the player can do, bound to keys, mouse and gamepad buttons by `Bindings`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Paint,
+    Erase,
+    PaintSand,
+    Pan,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    ZoomIn,
+    ZoomOut,
+    ToolCircle,
+    ToolSquare,
+    ToolLine,
+    ToolFill,
+    ToolSpray,
+    ToolDig,
+    ToolSelect,
+    NextMaterial,
+    PreviousMaterial,
+    Copy,
+    Paste,
+    Undo,
+    Redo,
+    ToggleDebug,
+    Quit,
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "paint" => Ok(Action::Paint),
+            "erase" => Ok(Action::Erase),
+            "paint_sand" => Ok(Action::PaintSand),
+            "pan" => Ok(Action::Pan),
+            "pan_left" => Ok(Action::PanLeft),
+            "pan_right" => Ok(Action::PanRight),
+            "pan_up" => Ok(Action::PanUp),
+            "pan_down" => Ok(Action::PanDown),
+            "cursor_left" => Ok(Action::CursorLeft),
+            "cursor_right" => Ok(Action::CursorRight),
+            "cursor_up" => Ok(Action::CursorUp),
+            "cursor_down" => Ok(Action::CursorDown),
+            "zoom_in" => Ok(Action::ZoomIn),
+            "zoom_out" => Ok(Action::ZoomOut),
+            "tool_circle" => Ok(Action::ToolCircle),
+            "tool_square" => Ok(Action::ToolSquare),
+            "tool_line" => Ok(Action::ToolLine),
+            "tool_fill" => Ok(Action::ToolFill),
+            "tool_spray" => Ok(Action::ToolSpray),
+            "tool_dig" => Ok(Action::ToolDig),
+            "tool_select" => Ok(Action::ToolSelect),
+            "next_material" => Ok(Action::NextMaterial),
+            "previous_material" => Ok(Action::PreviousMaterial),
+            "copy" => Ok(Action::Copy),
+            "paste" => Ok(Action::Paste),
+            "undo" => Ok(Action::Undo),
+            "redo" => Ok(Action::Redo),
+            "toggle_debug" => Ok(Action::ToggleDebug),
+            "quit" => Ok(Action::Quit),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(Scancode),
+    Mouse(MouseButton),
+    Button(Button),
+    /// Gamepad axis pushed in the positive (`true`) or negative direction
+    Axis(Axis, bool),
+}
+
+impl FromStr for Binding {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mouse_left" => Ok(Binding::Mouse(MouseButton::Left)),
+            "mouse_middle" => Ok(Binding::Mouse(MouseButton::Middle)),
+            "mouse_right" => Ok(Binding::Mouse(MouseButton::Right)),
+            "mouse_x1" => Ok(Binding::Mouse(MouseButton::X1)),
+            "mouse_x2" => Ok(Binding::Mouse(MouseButton::X2)),
+            name => match name.strip_prefix("pad_") {
+                Some(name) => match (name.strip_suffix('+'), name.strip_suffix('-')) {
+                    (Some(axis), _) => {
+                        Axis::from_string(axis).map(|axis| Binding::Axis(axis, true))
+                    }
+                    (_, Some(axis)) => {
+                        Axis::from_string(axis).map(|axis| Binding::Axis(axis, false))
+                    }
+                    _ => Button::from_string(name)
+                        .map(Binding::Button)
+                        .or_else(|| Axis::from_string(name).map(|axis| Binding::Axis(axis, true))),
+                },
+                None => Scancode::from_name(name).map(Binding::Key),
+            }
+            .ok_or(()),
+        }
+    }
+}
+
+/// Keys, mouse and gamepad buttons of every action
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    actions: HashMap<Action, Vec<Binding>>,
+}
+
+impl Bindings {
+    pub const DATA_PATH: &'static str = "./assets/bindings.cfg";
+
+    pub fn new() -> Bindings {
+        Bindings {
+            actions: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Bindings> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse bindings from the data file format, see `assets/bindings.cfg`
+    pub fn parse(source: &str) -> io::Result<Bindings> {
+        let mut bindings = Bindings::new();
+        for (line_index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            let error = |message: &str| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Line {}: {message}", line_index + 1),
+                )
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (action, value) = match line.split_once('=') {
+                Some((action, value)) => (action.trim(), value.trim()),
+                None => return Err(error("Expected <action> = <binding>, <binding>")),
+            };
+            let action: Action = action
+                .parse()
+                .map_err(|_| error(&format!("Unknown action {action}")))?;
+            for name in value.split(',').map(|name| name.trim()) {
+                let binding: Binding = name
+                    .parse()
+                    .map_err(|_| error(&format!("Unknown key or button {name}")))?;
+                // One button can't do two things at once, like painting and panning
+                match bindings.bound_action(binding) {
+                    Some(other) if other != action => {
+                        return Err(error(&format!("{name} is already bound to {other:?}")))
+                    }
+                    _ => bindings.bind(action, binding),
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.actions.entry(action).or_default().push(binding);
+    }
+
+    /// Action that `binding` is bound to, if any
+    pub fn bound_action(&self, binding: Binding) -> Option<Action> {
+        self.actions
+            .iter()
+            .find(|(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.actions.remove(&action);
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        match self.actions.get(&action) {
+            Some(bindings) => bindings,
+            None => &[],
+        }
+    }
+}
+
+impl Default for Bindings {
+    /// Bindings bundled into the executable, the same as in `Bindings::DATA_PATH`
+    fn default() -> Self {
+        match Self::parse(include_str!("../../assets/bindings.cfg")) {
+            Ok(bindings) => bindings,
+            Err(error) => panic!("Failed to parse bundled bindings: {error:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdl2::{
+        controller::{Axis, Button},
+        keyboard::Scancode,
+    };
+
+    use super::{Action, Binding, Bindings};
+    use crate::resources::MouseButton;
+
+    #[test]
+    fn parse() {
+        let bindings = Bindings::parse(
+            "
+            # comment
+            paint = mouse_left, Space
+            quit = Escape
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.get(Action::Paint),
+            &[
+                Binding::Mouse(MouseButton::Left),
+                Binding::Key(Scancode::Space)
+            ]
+        );
+        assert_eq!(
+            bindings.get(Action::Quit),
+            &[Binding::Key(Scancode::Escape)]
+        );
+        assert!(bindings.get(Action::Pan).is_empty());
+
+        let bindings =
+            Bindings::parse("paint = pad_righttrigger, pad_a\npan_left = pad_rightx-").unwrap();
+        assert_eq!(
+            bindings.get(Action::Paint),
+            &[
+                Binding::Axis(Axis::TriggerRight, true),
+                Binding::Button(Button::A)
+            ]
+        );
+        assert_eq!(
+            bindings.get(Action::PanLeft),
+            &[Binding::Axis(Axis::RightX, false)]
+        );
+
+        assert!(Bindings::parse("dance = Space").is_err());
+        assert!(Bindings::parse("paint = pad_jump").is_err());
+        assert!(Bindings::parse("paint = not a key").is_err());
+        assert!(Bindings::parse("paint = mouse_left\npan = mouse_left").is_err());
+    }
+
+    #[test]
+    fn bundled_bindings() {
+        let bindings = Bindings::default();
+        assert_eq!(
+            bindings.get(Action::ToggleDebug),
+            &[Binding::Key(Scancode::F3), Binding::Button(Button::Back)]
+        );
+        assert_eq!(bindings.get(Action::ZoomIn).len(), 3);
+        assert_eq!(
+            bindings.bound_action(Binding::Mouse(MouseButton::Middle)),
+            Some(Action::Pan)
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    mem::size_of,
+};
+
+use super::Terrain;
+use crate::{mst::texel::TexelID, util::Vector2I};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TexelEdit {
+    pub position: Vector2I,
+    pub old: TexelID,
+    pub new: TexelID,
+}
+
+/// Texel changes of one brush stroke, each position at most once
+#[derive(Clone, Debug, Default)]
+pub struct Stroke {
+    pub edits: Vec<TexelEdit>,
+}
+
+impl Stroke {
+    /// Keep the first old and the last new id of every position, and drop positions that ended up unchanged
+    fn merge(edits: Vec<TexelEdit>) -> Stroke {
+        let mut merged: Vec<TexelEdit> = Vec::with_capacity(edits.len());
+        let mut positions: HashMap<Vector2I, usize> = HashMap::new();
+        for edit in edits {
+            match positions.get(&edit.position) {
+                Some(i) => merged[*i].new = edit.new,
+                None => {
+                    positions.insert(edit.position, merged.len());
+                    merged.push(edit);
+                }
+            }
+        }
+        merged.retain(|edit| edit.old != edit.new);
+        Stroke { edits: merged }
+    }
+
+    fn byte_size(&self) -> usize {
+        size_of::<Stroke>() + self.edits.len() * size_of::<TexelEdit>()
+    }
+}
+
+/// Undo and redo of terrain edits, grouped into strokes.
+///
+/// Edits are applied through `Terrain::set_texel`, so rendering and collisions update as usual.
+/// The oldest strokes are forgotten when the history grows over its memory budget.
+#[derive(Debug)]
+pub struct EditHistory {
+    undo_stack: VecDeque<Stroke>,
+    redo_stack: Vec<Stroke>,
+    /// Edits of the stroke that hasn't ended yet
+    current: Vec<TexelEdit>,
+    budget: usize,
+    byte_size: usize,
+}
+
+impl EditHistory {
+    pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;
+
+    pub fn new() -> EditHistory {
+        Self::with_budget(Self::DEFAULT_BUDGET)
+    }
+
+    /// History that keeps at most `budget` bytes of strokes
+    pub fn with_budget(budget: usize) -> EditHistory {
+        EditHistory {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            current: Vec::new(),
+            budget,
+            byte_size: 0,
+        }
+    }
+
+    /// Add edits to the current stroke
+    pub fn record(&mut self, edits: Vec<TexelEdit>) {
+        self.current.extend(edits);
+    }
+
+    /// Finish the current stroke so that it can be undone. New strokes clear the redo history.
+    pub fn end_stroke(&mut self) {
+        if self.current.is_empty() {
+            return;
+        }
+        let stroke = Stroke::merge(std::mem::take(&mut self.current));
+        if stroke.edits.is_empty() {
+            return;
+        }
+        self.redo_stack.clear();
+        self.push_undo(stroke);
+    }
+
+    fn push_undo(&mut self, stroke: Stroke) {
+        self.byte_size += stroke.byte_size();
+        self.undo_stack.push_back(stroke);
+        while self.byte_size > self.budget {
+            match self.undo_stack.pop_front() {
+                Some(stroke) => self.byte_size -= stroke.byte_size(),
+                None => break,
+            }
+        }
+    }
+
+    /// Revert the last stroke, returns false if there is nothing to undo
+    pub fn undo(&mut self, terrain: &mut Terrain) -> bool {
+        self.end_stroke();
+        match self.undo_stack.pop_back() {
+            Some(stroke) => {
+                self.byte_size -= stroke.byte_size();
+                for edit in stroke.edits.iter().rev() {
+                    terrain.set_texel(&edit.position, edit.old);
+                }
+                self.redo_stack.push(stroke);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the last undone stroke again, returns false if there is nothing to redo
+    pub fn redo(&mut self, terrain: &mut Terrain) -> bool {
+        self.end_stroke();
+        match self.redo_stack.pop() {
+            Some(stroke) => {
+                for edit in stroke.edits.iter() {
+                    terrain.set_texel(&edit.position, edit.new);
+                }
+                self.push_undo(stroke);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    pub fn redo_count(&self) -> usize {
+        self.redo_stack.len()
+    }
+
+    /// Approximate memory used by the undoable strokes
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{EditHistory, TexelEdit};
+    use crate::{mst::chunk::Chunk, resources::Terrain, util::Vector2I};
+
+    fn id_at(terrain: &Terrain, x: i32, y: i32) -> u8 {
+        terrain.global_to_texel(&Vector2I { x, y }).unwrap().id
+    }
+
+    fn stroke(terrain: &mut Terrain, history: &mut EditHistory, xs: &[i32], id: u8) {
+        terrain.start_recording();
+        for x in xs {
+            terrain.set_texel(&Vector2I { x: *x, y: 0 }, id);
+        }
+        history.record(terrain.stop_recording());
+        history.end_stroke();
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut terrain = Terrain::from_chunks(HashMap::from([(Vector2I::ZERO, Chunk::new())]));
+        let mut history = EditHistory::new();
+        stroke(&mut terrain, &mut history, &[0, 1, 2], 1);
+        stroke(&mut terrain, &mut history, &[2, 3], 2);
+        assert_eq!(history.undo_count(), 2);
+
+        assert!(history.undo(&mut terrain));
+        assert_eq!(id_at(&terrain, 2, 0), 1);
+        assert_eq!(id_at(&terrain, 3, 0), 0);
+        assert!(history.undo(&mut terrain));
+        assert_eq!(id_at(&terrain, 0, 0), 0);
+        assert!(!history.undo(&mut terrain));
+
+        assert!(history.redo(&mut terrain));
+        assert_eq!(id_at(&terrain, 2, 0), 1);
+        // A new stroke clears the redo history
+        stroke(&mut terrain, &mut history, &[5], 3);
+        assert_eq!(history.redo_count(), 0);
+        assert!(!history.redo(&mut terrain));
+
+        // Edits outside of recording aren't part of the history
+        terrain.set_texel(&Vector2I { x: 8, y: 0 }, 4);
+        assert!(history.undo(&mut terrain));
+        assert_eq!(id_at(&terrain, 5, 0), 0);
+        assert_eq!(id_at(&terrain, 8, 0), 4);
+    }
+
+    #[test]
+    fn merged_edits() {
+        let mut terrain = Terrain::from_chunks(HashMap::from([(Vector2I::ZERO, Chunk::new())]));
+        let mut history = EditHistory::new();
+        terrain.start_recording();
+        terrain.set_texel(&Vector2I::ZERO, 1);
+        terrain.set_texel(&Vector2I::ZERO, 2);
+        terrain.set_texel(&Vector2I::ONE, 1);
+        terrain.set_texel(&Vector2I::ONE, 0);
+        let edits = terrain.stop_recording();
+        assert_eq!(edits.len(), 4);
+        history.record(edits);
+        history.end_stroke();
+
+        assert!(history.undo(&mut terrain));
+        assert_eq!(id_at(&terrain, 0, 0), 0);
+        assert!(history.redo(&mut terrain));
+        assert_eq!(id_at(&terrain, 0, 0), 2);
+        assert_eq!(
+            history.undo_stack.back().unwrap().edits,
+            vec![TexelEdit {
+                position: Vector2I::ZERO,
+                old: 0,
+                new: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn memory_budget() {
+        let mut terrain = Terrain::from_chunks(HashMap::from([(Vector2I::ZERO, Chunk::new())]));
+        let mut history = EditHistory::with_budget(1024);
+        for i in 0..32 {
+            let xs: Vec<i32> = (0..16).collect();
+            stroke(&mut terrain, &mut history, &xs, i % 2 + 1);
+        }
+        assert!(history.byte_size() <= 1024);
+        assert!(history.undo_count() > 0 && history.undo_count() < 32);
+        while history.undo(&mut terrain) {}
+        // The oldest strokes are gone, so the terrain can't go all the way back
+        assert_ne!(id_at(&terrain, 0, 0), 0);
+    }
+}
@@ -1,4 +1,11 @@
-use crate::util::Vector2I;
+use sdl2::{
+    controller::{Axis, Button},
+    event::Event,
+    keyboard::Scancode,
+};
+
+use super::{Action, Binding, Bindings};
+use crate::util::{Vector2F, Vector2I};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct MouseState {
@@ -26,7 +33,7 @@ impl MouseState {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MouseButton {
     None = 0,
     Left = 1,
@@ -49,25 +56,248 @@ impl From<sdl2::mouse::MouseButton> for MouseButton {
     }
 }
 
+/// Held keys, one bit per scancode
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyboardState {
+    keys: [u64; 8],
+}
+
+impl KeyboardState {
+    pub fn from_bits(keys: [u64; 8]) -> KeyboardState {
+        KeyboardState { keys }
+    }
+
+    pub fn bits(&self) -> [u64; 8] {
+        self.keys
+    }
+
+    pub fn get_key_state(&self, scancode: Scancode) -> bool {
+        let index = scancode as usize;
+        index < 512 && self.keys[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn set_key_state(&mut self, scancode: Scancode, state: bool) {
+        let index = scancode as usize;
+        if index >= 512 {
+            return;
+        }
+        match state {
+            true => self.keys[index / 64] |= 1 << (index % 64),
+            false => self.keys[index / 64] &= !(1 << (index % 64)),
+        }
+    }
+}
+
+/// Buttons and axes of all connected game controllers combined
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GamepadState {
+    buttons: u32,
+    /// From -1 to 1 for sticks and 0 to 1 for triggers, with the deadzone already applied
+    axes: [f32; 6],
+}
+
+impl GamepadState {
+    pub const STICK_DEADZONE: f32 = 0.2;
+    pub const TRIGGER_DEADZONE: f32 = 0.1;
+
+    /// State from button bits and axes that already have the deadzone applied
+    pub fn from_raw(buttons: u32, axes: [f32; 6]) -> GamepadState {
+        GamepadState { buttons, axes }
+    }
+
+    pub fn raw(&self) -> (u32, [f32; 6]) {
+        (self.buttons, self.axes)
+    }
+
+    pub fn get_button_state(&self, button: Button) -> bool {
+        self.buttons & (1 << button as u32) != 0
+    }
+
+    pub fn set_button_state(&mut self, button: Button, state: bool) {
+        match state {
+            true => self.buttons |= 1 << button as u32,
+            false => self.buttons &= !(1 << button as u32),
+        }
+    }
+
+    pub fn get_axis(&self, axis: Axis) -> f32 {
+        self.axes[axis as usize]
+    }
+
+    /// Values inside the deadzone become 0, the rest is rescaled to start from 0 at its edge
+    pub fn set_axis(&mut self, axis: Axis, value: f32) {
+        let deadzone = match axis {
+            Axis::TriggerLeft | Axis::TriggerRight => Self::TRIGGER_DEADZONE,
+            _ => Self::STICK_DEADZONE,
+        };
+        let value = value.clamp(-1.0, 1.0);
+        self.axes[axis as usize] = match value.abs() <= deadzone {
+            true => 0.0,
+            false => value.signum() * (value.abs() - deadzone) / (1.0 - deadzone),
+        };
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct InputState {
     pub mouse: MouseState,
+    pub keyboard: KeyboardState,
+    pub gamepad: GamepadState,
+}
+
+impl InputState {
+    /// State for the next frame, keeping held buttons and keys but not the scrolling
+    pub fn next_frame(&self) -> InputState {
+        let mut state = *self;
+        state.mouse.scroll = Vector2I::ZERO;
+        state
+    }
+
+    /// Update the state with an SDL event
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::MouseButtonDown { mouse_btn, .. } => {
+                let button = MouseButton::from(*mouse_btn);
+                self.mouse.set_button_state(&button, true);
+            }
+            Event::MouseButtonUp { mouse_btn, .. } => {
+                let button = MouseButton::from(*mouse_btn);
+                self.mouse.set_button_state(&button, false);
+            }
+            Event::MouseWheel { x, y, .. } => {
+                self.mouse.scroll = Vector2I { x: *x, y: *y };
+            }
+            Event::MouseMotion {
+                x, y, xrel, yrel, ..
+            } => {
+                self.mouse.position = Vector2I { x: *x, y: *y };
+                self.mouse.velocity = Vector2I { x: *xrel, y: *yrel };
+            }
+            Event::KeyDown {
+                scancode: Some(scancode),
+                ..
+            } => self.keyboard.set_key_state(*scancode, true),
+            Event::KeyUp {
+                scancode: Some(scancode),
+                ..
+            } => self.keyboard.set_key_state(*scancode, false),
+            Event::ControllerButtonDown { button, .. } => {
+                self.gamepad.set_button_state(*button, true)
+            }
+            Event::ControllerButtonUp { button, .. } => {
+                self.gamepad.set_button_state(*button, false)
+            }
+            Event::ControllerAxisMotion { axis, value, .. } => self
+                .gamepad
+                .set_axis(*axis, *value as f32 / i16::MAX as f32),
+            Event::ControllerDeviceRemoved { .. } => self.gamepad = GamepadState::default(),
+            _ => {}
+        }
+    }
+
+    /// How far the binding is pushed, from 0 to 1
+    pub fn value(&self, binding: &Binding) -> f32 {
+        match binding {
+            Binding::Key(scancode) => self.keyboard.get_key_state(*scancode) as i32 as f32,
+            Binding::Mouse(button) => self.mouse.get_button_state(button) as i32 as f32,
+            Binding::Button(button) => self.gamepad.get_button_state(*button) as i32 as f32,
+            Binding::Axis(axis, positive) => match positive {
+                true => self.gamepad.get_axis(*axis).max(0.0),
+                false => (-self.gamepad.get_axis(*axis)).max(0.0),
+            },
+        }
+    }
+
+    /// Axes count as held when pushed more than halfway
+    pub fn is_held(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Axis(..) => self.value(binding) > 0.5,
+            _ => self.value(binding) > 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct Input {
     prev_state: InputState,
     curr_state: InputState,
+    bindings: Bindings,
+    /// Follows the mouse, or moves with the cursor actions when the mouse isn't moving
+    cursor_position: Vector2F,
 }
 
 impl Input {
+    /// Pixels per frame the cursor moves with the cursor actions fully pushed
+    pub const CURSOR_SPEED: f32 = 8.0;
+
     pub fn new() -> Input {
+        Input::with_bindings(Bindings::default())
+    }
+
+    pub fn with_bindings(bindings: Bindings) -> Input {
         Input {
             prev_state: InputState::default(),
             curr_state: InputState::default(),
+            bindings,
+            cursor_position: Vector2F::ZERO,
         }
     }
 
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    fn action_state(&self, state: &InputState, action: Action) -> bool {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|binding| state.is_held(binding))
+    }
+
+    /// How far any binding of the action is pushed, from 0 to 1
+    pub fn action_value(&self, action: Action) -> f32 {
+        self.bindings
+            .get(action)
+            .iter()
+            .map(|binding| self.curr_state.value(binding))
+            .fold(0.0, f32::max)
+    }
+
+    /// From -1 to 1, combining a pair of opposite actions
+    pub fn action_axis(&self, negative: Action, positive: Action) -> f32 {
+        self.action_value(positive) - self.action_value(negative)
+    }
+
+    pub fn action_held(&self, action: Action) -> bool {
+        self.action_state(&self.curr_state, action)
+    }
+
+    pub fn action_pressed(&self, action: Action) -> bool {
+        !self.action_state(&self.prev_state, action) && self.action_state(&self.curr_state, action)
+    }
+
+    pub fn action_released(&self, action: Action) -> bool {
+        self.action_state(&self.prev_state, action) && !self.action_state(&self.curr_state, action)
+    }
+
+    pub fn key_held(&self, scancode: Scancode) -> bool {
+        self.curr_state.keyboard.get_key_state(scancode)
+    }
+
+    pub fn key_pressed(&self, scancode: Scancode) -> bool {
+        !self.prev_state.keyboard.get_key_state(scancode)
+            && self.curr_state.keyboard.get_key_state(scancode)
+    }
+
+    pub fn key_released(&self, scancode: Scancode) -> bool {
+        self.prev_state.keyboard.get_key_state(scancode)
+            && !self.curr_state.keyboard.get_key_state(scancode)
+    }
+
     pub fn mouse_held(&self, button: MouseButton) -> bool {
         self.curr_state.mouse.get_button_state(&button)
     }
@@ -94,9 +324,23 @@ impl Input {
         self.curr_state.mouse.scroll
     }
 
+    pub fn get_cursor_position(&self) -> Vector2I {
+        self.cursor_position.rounded()
+    }
+
     pub fn push_state(&mut self, state: InputState) {
         self.prev_state = self.curr_state;
         self.curr_state = state;
+
+        if self.curr_state.mouse.position != self.prev_state.mouse.position {
+            self.cursor_position = Vector2F::from(self.curr_state.mouse.position);
+        } else {
+            let direction = Vector2F {
+                x: self.action_axis(Action::CursorLeft, Action::CursorRight),
+                y: self.action_axis(Action::CursorUp, Action::CursorDown),
+            };
+            self.cursor_position = self.cursor_position + direction * Self::CURSOR_SPEED;
+        }
     }
 
     pub fn prev_state(&self) -> &InputState {
@@ -107,3 +351,93 @@ impl Input {
         &self.curr_state
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sdl2::{
+        controller::{Axis, Button},
+        event::Event,
+        keyboard::{Mod, Scancode},
+    };
+
+    use super::{Input, InputState};
+    use crate::{resources::Action, util::Vector2I};
+
+    fn key_event(scancode: Scancode, down: bool) -> Event {
+        match down {
+            true => Event::KeyDown {
+                timestamp: 0,
+                window_id: 0,
+                keycode: None,
+                scancode: Some(scancode),
+                keymod: Mod::NOMOD,
+                repeat: false,
+            },
+            false => Event::KeyUp {
+                timestamp: 0,
+                window_id: 0,
+                keycode: None,
+                scancode: Some(scancode),
+                keymod: Mod::NOMOD,
+                repeat: false,
+            },
+        }
+    }
+
+    #[test]
+    fn keyboard_actions() {
+        let mut input = Input::new();
+        let mut state = InputState::default();
+
+        state.handle_event(&key_event(Scancode::F3, true));
+        input.push_state(state.next_frame());
+        assert!(input.key_pressed(Scancode::F3));
+        assert!(input.action_pressed(Action::ToggleDebug));
+        assert!(!input.action_held(Action::Quit));
+
+        input.push_state(input.curr_state().next_frame());
+        assert!(input.action_held(Action::ToggleDebug));
+        assert!(!input.action_pressed(Action::ToggleDebug));
+
+        state.handle_event(&key_event(Scancode::F3, false));
+        input.push_state(state);
+        assert!(input.action_released(Action::ToggleDebug));
+        assert!(!input.key_held(Scancode::F3));
+    }
+
+    #[test]
+    fn gamepad_actions() {
+        let mut input = Input::new();
+        let mut state = InputState::default();
+
+        state.gamepad.set_axis(Axis::LeftX, 0.1);
+        state.gamepad.set_axis(Axis::TriggerRight, 0.05);
+        input.push_state(state);
+        assert_eq!(input.curr_state().gamepad.get_axis(Axis::LeftX), 0.0);
+        assert_eq!(input.action_value(Action::Paint), 0.0);
+        assert_eq!(input.get_cursor_position(), Vector2I::ZERO);
+
+        state.gamepad.set_axis(Axis::LeftX, 1.0);
+        state.gamepad.set_axis(Axis::TriggerRight, 0.8);
+        state.gamepad.set_button_state(Button::RightShoulder, true);
+        input.push_state(state);
+        assert_eq!(
+            input.action_axis(Action::CursorLeft, Action::CursorRight),
+            1.0
+        );
+        assert!(input.action_held(Action::Paint));
+        assert!(input.action_pressed(Action::ZoomIn));
+        assert_eq!(
+            input.get_cursor_position(),
+            Vector2I {
+                x: Input::CURSOR_SPEED as i32,
+                y: 0
+            }
+        );
+
+        // Moving the mouse takes the cursor back
+        state.mouse.position = Vector2I { x: 100, y: 50 };
+        input.push_state(state);
+        assert_eq!(input.get_cursor_position(), Vector2I { x: 100, y: 50 });
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{mst::texel::TexelID, util::Vector2I};
+
+pub type MaterialColor = (u8, u8, u8, u8);
+
+/// How texels of a material move in the terrain simulation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaterialBehaviour {
+    Static,
+    /// Falls down and piles up, like sand
+    Powder,
+    /// Falls down and flows sideways, like water
+    Liquid,
+}
+
+impl FromStr for MaterialBehaviour {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "static" => Ok(MaterialBehaviour::Static),
+            "powder" => Ok(MaterialBehaviour::Powder),
+            "liquid" => Ok(MaterialBehaviour::Liquid),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// Render colour, or a palette that is picked from by texel position
+    pub colors: Vec<MaterialColor>,
+    /// Extra colours that map to this material when importing terrain images
+    pub image_colors: Vec<(u8, u8, u8)>,
+    pub density: f32,
+    pub friction: f32,
+    pub hardness: f32,
+    /// Whether the material is solid ground
+    pub solid: bool,
+    /// Whether the material produces collision shapes
+    pub collides: bool,
+    /// Holds up the terrain connected to it, like bedrock
+    pub anchored: bool,
+    pub behaviour: MaterialBehaviour,
+}
+
+impl Material {
+    pub const MISSING_COLOR: MaterialColor = (255, 0, 255, 255);
+
+    pub fn new(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            colors: vec![],
+            image_colors: vec![],
+            density: 1.0,
+            friction: 0.3,
+            hardness: 1.0,
+            solid: true,
+            collides: true,
+            anchored: false,
+            behaviour: MaterialBehaviour::Static,
+        }
+    }
+
+    pub fn color_at(&self, position: &Vector2I) -> MaterialColor {
+        match self.colors.len() {
+            0 => Self::MISSING_COLOR,
+            1 => self.colors[0],
+            len => {
+                let hash = (position.x as u32).wrapping_mul(73_856_093)
+                    ^ (position.y as u32).wrapping_mul(19_349_663);
+                self.colors[hash as usize % len]
+            }
+        }
+    }
+}
+
+/// Registry of terrain materials keyed by texel id
+pub struct Materials {
+    materials: Vec<Option<Material>>,
+}
+
+impl Materials {
+    pub const DATA_PATH: &'static str = "./assets/materials.cfg";
+
+    pub fn new() -> Materials {
+        Materials {
+            materials: vec![None; TexelID::MAX as usize + 1],
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Materials> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse materials from the data file format, see `assets/materials.cfg`
+    pub fn parse(source: &str) -> io::Result<Materials> {
+        let mut materials = Materials::new();
+        let mut current: Option<(TexelID, Material)> = None;
+
+        for (line_index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            let error = |message: &str| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Line {}: {message}", line_index + 1),
+                )
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                match current.take() {
+                    Some((id, material)) => materials.insert(id, material),
+                    None => (),
+                }
+                let header: Vec<&str> = line[1..line.len() - 1].split_whitespace().collect();
+                if header.len() != 2 {
+                    return Err(error("Expected section header [<id> <name>]"));
+                }
+                let id: TexelID = header[0]
+                    .parse()
+                    .map_err(|_| error("Material id must be between 0 and 255"))?;
+                current = Some((id, Material::new(header[1])));
+                continue;
+            }
+
+            let material = match current.as_mut() {
+                Some((_, material)) => material,
+                None => return Err(error("Property outside of a material section")),
+            };
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(error("Expected <key> = <value>")),
+            };
+            match key {
+                "color" => {
+                    material.colors = Self::parse_list(value)
+                        .iter()
+                        .map(|color| match color[..] {
+                            [r, g, b, a] => Ok((r, g, b, a)),
+                            _ => Err(error("Colors must be in format r g b a")),
+                        })
+                        .collect::<io::Result<Vec<MaterialColor>>>()?
+                }
+                "image_colors" => {
+                    material.image_colors = Self::parse_list(value)
+                        .iter()
+                        .map(|color| match color[..] {
+                            [r, g, b] => Ok((r, g, b)),
+                            _ => Err(error("Image colors must be in format r g b")),
+                        })
+                        .collect::<io::Result<Vec<(u8, u8, u8)>>>()?
+                }
+                "density" => material.density = Self::parse_value(value, &error)?,
+                "friction" => material.friction = Self::parse_value(value, &error)?,
+                "hardness" => material.hardness = Self::parse_value(value, &error)?,
+                "solid" => material.solid = Self::parse_value(value, &error)?,
+                "collides" => material.collides = Self::parse_value(value, &error)?,
+                "anchored" => material.anchored = Self::parse_value(value, &error)?,
+                "behaviour" => material.behaviour = Self::parse_value(value, &error)?,
+                _ => return Err(error(&format!("Unknown property {key}"))),
+            }
+        }
+        match current {
+            Some((id, material)) => materials.insert(id, material),
+            None => (),
+        }
+
+        Ok(materials)
+    }
+
+    /// Comma separated list of space separated numbers. Invalid numbers become empty entries.
+    fn parse_list(value: &str) -> Vec<Vec<u8>> {
+        value
+            .split(',')
+            .map(|entry| {
+                entry
+                    .split_whitespace()
+                    .map(|number| number.parse::<u8>())
+                    .collect::<Result<Vec<u8>, _>>()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    fn parse_value<T: std::str::FromStr>(
+        value: &str,
+        error: &dyn Fn(&str) -> Error,
+    ) -> io::Result<T> {
+        value
+            .parse()
+            .map_err(|_| error(&format!("Invalid value {value}")))
+    }
+
+    pub fn insert(&mut self, id: TexelID, material: Material) {
+        self.materials[id as usize] = Some(material);
+    }
+
+    pub fn get(&self, id: TexelID) -> Option<&Material> {
+        self.materials[id as usize].as_ref()
+    }
+
+    pub fn find(&self, name: &str) -> Option<TexelID> {
+        self.iter()
+            .find(|(_, material)| material.name == name)
+            .map(|(id, _)| id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (TexelID, &Material)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .filter_map(|(id, material)| {
+                material.as_ref().map(|material| (id as TexelID, material))
+            })
+    }
+
+    pub fn color_at(&self, id: TexelID, position: &Vector2I) -> MaterialColor {
+        match self.get(id) {
+            Some(material) => material.color_at(position),
+            None => Material::MISSING_COLOR,
+        }
+    }
+
+    /// Unknown materials are treated as colliding, except for the empty texel
+    pub fn collides(&self, id: TexelID) -> bool {
+        match self.get(id) {
+            Some(material) => material.collides,
+            None => id != 0,
+        }
+    }
+
+    /// Unknown materials are treated as solid, except for the empty texel
+    pub fn is_solid(&self, id: TexelID) -> bool {
+        match self.get(id) {
+            Some(material) => material.solid,
+            None => id != 0,
+        }
+    }
+
+    /// Solid and static, these texels form terrain that can break off
+    pub fn is_rigid(&self, id: TexelID) -> bool {
+        self.is_solid(id) && self.behaviour(id) == MaterialBehaviour::Static
+    }
+
+    pub fn is_anchored(&self, id: TexelID) -> bool {
+        match self.get(id) {
+            Some(material) => material.anchored,
+            None => false,
+        }
+    }
+
+    /// Unknown materials are static
+    pub fn behaviour(&self, id: TexelID) -> MaterialBehaviour {
+        match self.get(id) {
+            Some(material) => material.behaviour,
+            None => MaterialBehaviour::Static,
+        }
+    }
+
+    /// Unknown materials have the default density
+    pub fn density(&self, id: TexelID) -> f32 {
+        match self.get(id) {
+            Some(material) => material.density,
+            None => 1.0,
+        }
+    }
+
+    /// Unknown materials have the default friction
+    pub fn friction(&self, id: TexelID) -> f32 {
+        match self.get(id) {
+            Some(material) => material.friction,
+            None => 0.3,
+        }
+    }
+}
+
+impl Default for Materials {
+    /// Materials bundled into the executable, the same as in `Materials::DATA_PATH`
+    fn default() -> Self {
+        match Self::parse(include_str!("../../assets/materials.cfg")) {
+            Ok(materials) => materials,
+            Err(error) => panic!("Failed to parse bundled materials: {error:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Material, MaterialBehaviour, Materials};
+    use crate::util::Vector2I;
+
+    #[test]
+    fn parse() {
+        let materials = Materials::parse(
+            "
+            # comment
+            [0 air]
+            color = 0 0 0 0
+            collides = false
+
+            [12 water]
+            color = 40 80 200 180, 50 90 210 180
+            image_colors = 0 0 255
+            density = 1.0
+            solid = false
+            collides = false
+            behaviour = liquid
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(materials.find("water"), Some(12));
+        let water = materials.get(12).unwrap();
+        assert_eq!(water.colors.len(), 2);
+        assert_eq!(water.image_colors, vec![(0, 0, 255)]);
+        assert!(!water.solid);
+        assert_eq!(water.behaviour, MaterialBehaviour::Liquid);
+        assert_eq!(materials.behaviour(0), MaterialBehaviour::Static);
+        assert!(!materials.collides(12));
+        assert!(!materials.collides(0));
+        // Unknown ids
+        assert!(materials.collides(200));
+        assert_eq!(
+            materials.color_at(200, &Vector2I::ZERO),
+            Material::MISSING_COLOR
+        );
+
+        assert!(Materials::parse("[1 dirt]\ncolor = 1 2 3").is_err());
+        assert!(Materials::parse("density = 1.0").is_err());
+        assert!(Materials::parse("[6 sand]\nbehaviour = gas").is_err());
+    }
+
+    #[test]
+    fn bundled_materials() {
+        let materials = Materials::default();
+        for name in [
+            "air", "dirt", "grass", "stone", "copper", "gold", "sand", "water",
+        ] {
+            assert!(materials.find(name).is_some(), "Missing material {name}");
+        }
+    }
+}
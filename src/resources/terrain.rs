@@ -1,18 +1,26 @@
-use std::collections::{
-    hash_map::{Iter, IterMut},
-    HashMap,
+use std::{
+    collections::{
+        hash_map::{Iter, IterMut},
+        HashMap,
+    },
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
 };
 
 use crate::{
     mst::{
         chunk::{Chunk, TexelUpdate},
+        persistence::{read_chunks, write_chunks},
         texel::{Texel, TexelID},
         utils::{global_to_index, global_to_local},
-        world_gen::gen_from_image,
+        world_gen::{gen_from_image, WorldGenerator, DATA_PATH},
     },
     util::{ChangeBuffer, Listener, Vector2I},
 };
 
+use super::{Materials, TexelEdit};
+
 #[derive(Clone)]
 pub enum TerrainUpdate {
     None,
@@ -30,19 +38,69 @@ impl Default for TerrainUpdate {
 #[derive(Default)]
 pub struct Terrain {
     chunk_map: HashMap<Vector2I, Chunk>,
+    /// Chunks that have been streamed out, kept so that edits survive reloading
+    unloaded_chunk_map: HashMap<Vector2I, Chunk>,
+    /// Generates chunks that have never been loaded before
+    generator: Option<WorldGenerator>,
     change_buffer: ChangeBuffer<TerrainUpdate>,
     /// Map a single listener to per-chunk listeners
     chunk_listener_map: HashMap<Listener, HashMap<Vector2I, Listener>>,
+    /// Changes made with `set_texel` while recording, see `start_recording`
+    edit_log: Option<Vec<TexelEdit>>,
 }
 
 impl Terrain {
-    pub fn new() -> Terrain {
+    pub fn new(materials: &Materials) -> Terrain {
+        Self::from_chunks(gen_from_image(DATA_PATH, materials))
+    }
+
+    /// Create a procedurally generated terrain
+    pub fn from_generator(generator: &WorldGenerator) -> Terrain {
+        Self::from_chunks(generator.gen_chunks())
+    }
+
+    /// Create an empty terrain where chunks are generated on demand with `load_chunk`
+    pub fn streamed(generator: WorldGenerator) -> Terrain {
+        let mut terrain = Self::from_chunks(HashMap::new());
+        terrain.generator = Some(generator);
+        terrain
+    }
+
+    /// Load a terrain saved with `save`
+    pub fn load(path: &Path) -> io::Result<Terrain> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(Self::from_chunks(read_chunks(&mut reader)?))
+    }
+
+    /// Save both loaded and unloaded chunks. The generator is not saved.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut chunks: Vec<(&Vector2I, &Chunk)> = self
+            .chunk_map
+            .iter()
+            .chain(self.unloaded_chunk_map.iter())
+            .collect();
+        chunks.sort_by_key(|(index, _)| (index.y, index.x));
+
+        // Write to a temporary file first so that a failed save doesn't corrupt the previous one
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            write_chunks(&mut writer, chunks)?;
+            writer.flush()?;
+        }
+        fs::rename(&temp_path, path)
+    }
+
+    pub fn from_chunks(mut chunks: HashMap<Vector2I, Chunk>) -> Terrain {
         let mut terrain = Terrain {
             chunk_map: HashMap::new(),
+            unloaded_chunk_map: HashMap::new(),
+            generator: None,
             chunk_listener_map: HashMap::new(),
             change_buffer: ChangeBuffer::new(),
+            edit_log: None,
         };
-        for (index, chunk) in gen_from_image().drain() {
+        for (index, chunk) in chunks.drain() {
             terrain.add_chunk(index, chunk);
         }
         terrain
@@ -60,6 +118,54 @@ impl Terrain {
         self.chunk_map.remove(&index);
     }
 
+    /// Load a previously unloaded chunk, or generate it if it has never been loaded.
+    ///
+    /// Returns false if the chunk isn't loaded and there is no way to create it.
+    pub fn load_chunk(&mut self, index: Vector2I) -> bool {
+        if self.is_chunk_loaded(&index) {
+            return true;
+        }
+        match self.take_unloaded_chunk(&index) {
+            Some(chunk) => {
+                self.add_chunk(index, chunk);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the chunk from the active chunks, but keep its texels around for `load_chunk`
+    pub fn unload_chunk(&mut self, index: Vector2I) {
+        match self.chunk_map.remove(&index) {
+            Some(mut chunk) => {
+                // Listeners of an unloaded chunk will never be consumed
+                chunk.change_buffer = ChangeBuffer::new();
+                self.unloaded_chunk_map.insert(index, chunk);
+                self.change_buffer
+                    .push_event(TerrainUpdate::ChunkRemoved(index));
+            }
+            None => (),
+        }
+    }
+
+    pub fn set_generator(&mut self, generator: Option<WorldGenerator>) {
+        self.generator = generator;
+    }
+
+    pub fn is_chunk_loaded(&self, index: &Vector2I) -> bool {
+        self.chunk_map.contains_key(index)
+    }
+
+    fn take_unloaded_chunk(&mut self, index: &Vector2I) -> Option<Chunk> {
+        match self.unloaded_chunk_map.remove(index) {
+            Some(chunk) => Some(chunk),
+            None => self
+                .generator
+                .as_ref()
+                .map(|generator| generator.gen_chunk(index)),
+        }
+    }
+
     pub fn chunk_iter(&self) -> Iter<Vector2I, Chunk> {
         self.chunk_map.iter()
     }
@@ -100,16 +206,41 @@ impl Terrain {
 
     pub fn set_texel(&mut self, global: &Vector2I, id: TexelID) {
         let index = global_to_index(global);
-        match self.index_to_chunk_mut(&index) {
-            Some(chunk) => chunk.set_texel(&global_to_local(global), id),
+        let local = global_to_local(global);
+        let old = match self.index_to_chunk_mut(&index) {
+            Some(chunk) => {
+                let old = chunk.get_texel(&local).map(|texel| texel.id);
+                chunk.set_texel(&local, id);
+                old
+            }
             None => {
-                let mut chunk = Chunk::new();
-                chunk.set_texel(&global_to_local(global), id);
+                let mut chunk = self.take_unloaded_chunk(&index).unwrap_or_else(Chunk::new);
+                let old = chunk.get_texel(&local).map(|texel| texel.id);
+                chunk.set_texel(&local, id);
                 self.add_chunk(index, chunk);
+                old
             }
+        };
+        match (&mut self.edit_log, old) {
+            (Some(edit_log), Some(old)) if old != id => edit_log.push(TexelEdit {
+                position: *global,
+                old,
+                new: id,
+            }),
+            _ => (),
         }
     }
 
+    /// Record the changes of every following `set_texel`, until `stop_recording`
+    pub fn start_recording(&mut self) {
+        self.edit_log = Some(Vec::new());
+    }
+
+    /// Changes since `start_recording`, in the order they were made
+    pub fn stop_recording(&mut self) -> Vec<TexelEdit> {
+        self.edit_log.take().unwrap_or_default()
+    }
+
     pub fn get_listener(&mut self) -> Listener {
         let listener = self.change_buffer.get_listener();
 
@@ -147,3 +278,68 @@ impl Terrain {
         self.change_buffer.consume_listener(listener)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Terrain, TerrainUpdate};
+    use crate::{
+        mst::{texel::Texel, world_gen::WorldGenerator},
+        resources::Materials,
+        util::{TempPath, Vector2I},
+    };
+
+    #[test]
+    fn unload_keeps_edits() {
+        let mut terrain = Terrain::streamed(WorldGenerator::new(7, &Materials::default()));
+        let index = Vector2I { x: 1, y: 2 };
+        let global = Vector2I { x: 40, y: 70 };
+
+        assert!(terrain.load_chunk(index));
+        terrain.set_texel(&global, 9);
+
+        let listener = terrain.get_listener();
+        terrain.unload_chunk(index);
+        assert!(!terrain.is_chunk_loaded(&index));
+        assert!(terrain.global_to_texel(&global).is_none());
+        match terrain.consume_changes(listener).unwrap().as_slice() {
+            [TerrainUpdate::ChunkRemoved(removed)] => assert_eq!(*removed, index),
+            _ => panic!("Expected a single ChunkRemoved event"),
+        }
+
+        assert!(terrain.load_chunk(index));
+        assert_eq!(terrain.global_to_texel(&global).unwrap().id, 9);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut generator = WorldGenerator::new(99, &Materials::default());
+        generator.size = Vector2I { x: 3, y: 4 };
+        let mut terrain = Terrain::from_generator(&generator);
+
+        // Dig a tunnel and fill it partially with another material
+        for x in 10..80 {
+            for y in 60..66 {
+                terrain.set_texel(&Vector2I { x, y }, Texel::EMPTY);
+            }
+            terrain.set_texel(&Vector2I { x, y: 65 }, 7);
+        }
+        // Paint outside of the generated area
+        terrain.set_texel(&Vector2I { x: -5, y: -5 }, 1);
+
+        let path = TempPath::new("dig-n-ripoff-save-and-load.terrain");
+        terrain.save(path.as_ref()).unwrap();
+        let loaded = Terrain::load(path.as_ref()).unwrap();
+
+        assert_eq!(loaded.chunk_iter().count(), terrain.chunk_iter().count());
+        for (index, chunk) in terrain.chunk_iter() {
+            let loaded_chunk = loaded.index_to_chunk(index).unwrap();
+            for i in 0..chunk.texels.len() {
+                assert_eq!(chunk.texels[i].id, loaded_chunk.texels[i].id);
+                assert_eq!(
+                    chunk.texels[i].neighbour_mask,
+                    loaded_chunk.texels[i].neighbour_mask
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use box2d_rs::b2_body::B2bodyType;
+use specs::{Builder, Dispatcher, DispatcherBuilder, World, WorldExt};
+
+use crate::{
+    components::{flags, ui, ChunkIndex, PhysicsBody, RenderTarget, Transform},
+    gl::{
+        renderer::{self, UnsafeCanvas},
+        texture_cache::{TextureCache, UnsafeTextureCache},
+    },
+    mst::{
+        marching_square::{CollisionOptions, MarchingMode},
+        world_gen::WorldGenerator,
+    },
+    resources::{
+        Bindings, Box2D, Camera, EditHistory, Input, InputState, Materials, Terrain, Time,
+        UnsafeBox2D,
+    },
+    systems,
+    util::{arg_value, box2d::create_box, has_flag, SortingOrder, Vector2, Vector2F},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct SimulationOptions {
+    /// Delta time of every `Simulation::step`
+    pub time_step: Duration,
+    /// Stream chunks within this many chunks of the camera, for terrain with a world generator
+    pub streaming_radius: Option<i32>,
+    pub collision: CollisionOptions,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        SimulationOptions {
+            time_step: Duration::new(0, 1_000_000_000u32 / 60),
+            streaming_radius: None,
+            collision: systems::TerrainCollision::new().options(),
+        }
+    }
+}
+
+/// The game world and its systems, stepped one frame at a time.
+///
+/// Without a canvas nothing is rendered, and the same terrain and inputs always give the same result.
+pub struct Simulation {
+    pub world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    options: SimulationOptions,
+}
+
+impl Simulation {
+    pub fn new(terrain: Terrain, materials: Materials, options: SimulationOptions) -> Simulation {
+        Self::build(terrain, materials, options, None)
+    }
+
+    /// Simulation that draws every step to the canvas
+    pub fn with_canvas(
+        terrain: Terrain,
+        materials: Materials,
+        options: SimulationOptions,
+        canvas: UnsafeCanvas,
+    ) -> Simulation {
+        Self::build(terrain, materials, options, Some(canvas))
+    }
+
+    /// Simulation of the game, set up from the command line arguments:
+    /// - `--seed <number>` streams a procedurally generated terrain instead of loading it from an image
+    /// - `--save <path>` continues from a saved terrain, see `replay::Session` for saving it
+    /// - `--smooth` gives diagonal collision outlines instead of following the texel edges
+    pub fn from_args(args: &[String], canvas: UnsafeCanvas) -> Simulation {
+        let seed: Option<u64> = arg_value(args, "--seed")
+            .map(|seed| seed.parse().expect("Seed must be a positive integer"));
+        let save_path: Option<PathBuf> = arg_value(args, "--save").map(PathBuf::from);
+        let mut options = SimulationOptions {
+            streaming_radius: seed.map(|_| 6),
+            ..SimulationOptions::default()
+        };
+        if has_flag(args, "--smooth") {
+            options.collision = CollisionOptions {
+                mode: MarchingMode::Interpolated,
+                ..options.collision
+            };
+        }
+
+        let materials = match Materials::load(Path::new(Materials::DATA_PATH)) {
+            Ok(materials) => materials,
+            Err(error) => panic!("Failed to load materials: {error:?}"),
+        };
+
+        let now = std::time::SystemTime::now();
+        let mut terrain = match &save_path {
+            Some(path) if path.exists() => match Terrain::load(path) {
+                Ok(terrain) => terrain,
+                Err(error) => panic!("Failed to load terrain from {}: {error:?}", path.display()),
+            },
+            _ => match seed {
+                Some(seed) => Terrain::streamed(WorldGenerator::new(seed, &materials)),
+                None => Terrain::new(&materials),
+            },
+        };
+        if seed.is_some() {
+            terrain.set_generator(seed.map(|seed| WorldGenerator::new(seed, &materials)));
+        }
+        match now.elapsed() {
+            Ok(elapsed) => println!("Creating chunks took {}ms", elapsed.as_millis()),
+            Err(error) => println!("Timer error: {:?}", error),
+        };
+
+        let mut simulation = Self::with_canvas(terrain, materials, options, canvas);
+        simulation.spawn_demo_boxes();
+
+        let bindings = match Bindings::load(Path::new(Bindings::DATA_PATH)) {
+            Ok(bindings) => bindings,
+            Err(error) => panic!("Failed to load bindings: {error:?}"),
+        };
+        simulation.world.fetch_mut::<Input>().set_bindings(bindings);
+        simulation
+    }
+
+    /// Pyramid of dynamic boxes above the terrain
+    pub fn spawn_demo_boxes(&mut self) {
+        let box2d_world = self.world.fetch::<UnsafeBox2D>().world_ptr.clone();
+
+        let pyramid_size = 1;
+        let center = 128.0;
+        let box_size = 12.0;
+        let separation = 1.5;
+        for y in 0..pyramid_size {
+            for x in 0..y + 1 {
+                let c: u8 = (x * 64 % 256) as u8;
+                let pos = Vector2F {
+                    x: center + (x as f32 - y as f32 / 2.0) * box_size * separation,
+                    y: y as f32 * box_size * separation + 16.0,
+                };
+                create_box(
+                    &mut self.world,
+                    box2d_world.clone(),
+                    B2bodyType::B2DynamicBody,
+                    pos,
+                    x as f32 * PI / 8.0,
+                    Vector2F::ONE * box_size,
+                    (255 - c, 255, c, 255),
+                )
+            }
+        }
+    }
+
+    fn build(
+        terrain: Terrain,
+        materials: Materials,
+        options: SimulationOptions,
+        canvas: Option<UnsafeCanvas>,
+    ) -> Simulation {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<ChunkIndex>();
+        world.register::<RenderTarget>();
+        world.register::<PhysicsBody>();
+        world.register::<ui::TextElement>();
+        world.register::<ui::ElementShadow>();
+        world.register::<flags::DebugText>();
+
+        world.insert(terrain);
+        world.insert(materials);
+        world.insert(Time::default());
+        world.insert(Camera {
+            transform: Transform::new(Vector2 { x: 0.0, y: 0.0 }, 0.0, Vector2 { x: 4.0, y: 4.0 }),
+        });
+        world.insert(Box2D::new_unsafe());
+        world.insert(Input::new());
+        world.insert(EditHistory::new());
+
+        let mut dispatcher_builder = DispatcherBuilder::new()
+            .with(systems::TerrainPainter::new(), "terrain_painter", &[])
+            .with(systems::CameraControl::new(), "camera_control", &[])
+            .with(
+                systems::TerrainSimulation::new(),
+                "terrain_simulation",
+                &["terrain_painter"],
+            );
+        match options.streaming_radius {
+            Some(radius) => dispatcher_builder.add(
+                systems::TerrainStreaming::new(radius),
+                "terrain_streaming",
+                &["camera_control"],
+            ),
+            None => (),
+        }
+        dispatcher_builder = dispatcher_builder
+            .with_thread_local(systems::TerrainSync::new())
+            .with_thread_local(systems::TerrainFragments::new())
+            .with_thread_local(systems::TerrainCollision::with_options(options.collision))
+            .with_thread_local(systems::Box2DPhysics::new());
+
+        match canvas {
+            Some(canvas) => {
+                world.insert(canvas);
+                world.insert(UnsafeTextureCache::new(TextureCache::new()));
+                dispatcher_builder.add(systems::debug::DebugInfo::new(), "debug_info", &[]);
+                dispatcher_builder = dispatcher_builder
+                    .with_thread_local(systems::TerrainRender::new())
+                    .with_thread_local(systems::ui::UIRender::new())
+                    .with_thread_local(systems::Render)
+                    .with_thread_local(systems::Box2DVisualizer::new());
+
+                world
+                    .create_entity()
+                    .with(Transform::IDENTITY.with_position(Vector2F { x: 4.0, y: 4.0 }))
+                    .with(RenderTarget::new(
+                        1,
+                        1,
+                        Vector2F::ZERO,
+                        SortingOrder::Ui as i16,
+                        true,
+                    ))
+                    .with(ui::TextElement::from_string("fps: 0"))
+                    .with(ui::ElementShadow::new())
+                    .with(flags::DebugText)
+                    .build();
+            }
+            None => (),
+        }
+
+        let mut dispatcher = dispatcher_builder.build();
+        dispatcher.setup(&mut world);
+        Simulation {
+            world,
+            dispatcher,
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &SimulationOptions {
+        &self.options
+    }
+
+    /// Frames stepped so far
+    pub fn frame(&self) -> u64 {
+        self.world.fetch::<Time>().frame
+    }
+
+    /// Step one frame with the fixed time step
+    pub fn step(&mut self, input: InputState) {
+        self.step_with_delta(input, self.options.time_step);
+    }
+
+    /// Step one frame that took `delta_time`, for running in real time
+    pub fn step_with_delta(&mut self, input: InputState, delta_time: Duration) {
+        self.world.fetch_mut::<Input>().push_state(input);
+        self.world.fetch_mut::<Time>().delta_time = delta_time;
+
+        match self.world.try_fetch_mut::<UnsafeCanvas>() {
+            Some(mut canvas) => renderer::begin_draw(&mut canvas),
+            None => (),
+        }
+
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+
+        match self.world.try_fetch_mut::<UnsafeCanvas>() {
+            Some(mut canvas) => renderer::finish_draw(&mut canvas),
+            None => (),
+        }
+
+        let mut time = self.world.fetch_mut::<Time>();
+        time.frame = (time.frame + 1) % u64::MAX;
+    }
+}
+
+impl Drop for Simulation {
+    /// The world drops its resources in any order, the textures have to go before the canvas
+    fn drop(&mut self) {
+        match self.world.try_fetch_mut::<UnsafeTextureCache>() {
+            Some(mut texture_cache) => texture_cache.clear(),
+            None => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Simulation, SimulationOptions};
+    use crate::{
+        mst::{chunk::Chunk, texel::TexelID},
+        resources::{InputState, Materials, MouseButton, MouseState, Terrain},
+        util::Vector2I,
+    };
+
+    fn simulation() -> Simulation {
+        let materials = Materials::default();
+        let mut terrain = Terrain::from_chunks(HashMap::from([
+            (Vector2I::ZERO, Chunk::new()),
+            (Vector2I::RIGHT, Chunk::new()),
+        ]));
+        let sand = materials.find("sand").unwrap();
+        for y in 0..8 {
+            for x in 10..14 {
+                terrain.set_texel(&Vector2I { x, y }, sand);
+            }
+        }
+        // Holds up the dirt painted in the right chunk, so it stays part of the terrain
+        let bedrock = materials.find("bedrock").unwrap();
+        for y in 24..Chunk::SIZE.y {
+            terrain.set_texel(&Vector2I { x: 40, y }, bedrock);
+        }
+        Simulation::new(terrain, materials, SimulationOptions::default())
+    }
+
+    fn texels(simulation: &Simulation) -> Vec<TexelID> {
+        let terrain = simulation.world.fetch::<Terrain>();
+        [Vector2I::ZERO, Vector2I::RIGHT]
+            .iter()
+            .flat_map(|index| terrain.index_to_chunk(index).unwrap().texels.iter())
+            .map(|texel| texel.id)
+            .collect()
+    }
+
+    #[test]
+    fn deterministic_steps() {
+        let materials = Materials::default();
+        let mut input = InputState::default();
+        // Paint a dirt circle in the right chunk with the mouse
+        input.mouse = MouseState {
+            position: Vector2I { x: 160, y: 80 },
+            ..MouseState::default()
+        };
+        input.mouse.set_button_state(&MouseButton::Left, true);
+
+        let mut a = simulation();
+        let mut b = simulation();
+        for frame in 0..120 {
+            let state = if frame < 10 {
+                input
+            } else {
+                InputState::default()
+            };
+            a.step(state);
+            b.step(state);
+        }
+        assert_eq!(a.frame(), 120);
+        assert_eq!(texels(&a), texels(&b));
+
+        let terrain = a.world.fetch::<Terrain>();
+        let dirt = materials.find("dirt").unwrap();
+        assert_eq!(
+            terrain
+                .global_to_texel(&Vector2I { x: 40, y: 20 })
+                .unwrap()
+                .id,
+            dirt
+        );
+        // The sand has fallen to the bottom of the chunk
+        let sand = materials.find("sand").unwrap();
+        assert_eq!(
+            terrain
+                .global_to_texel(&Vector2I { x: 11, y: 31 })
+                .unwrap()
+                .id,
+            sand
+        );
+    }
+}
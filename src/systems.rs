@@ -4,8 +4,11 @@ mod camera_control;
 pub mod debug;
 mod render;
 mod terrain_collisions;
+mod terrain_fragments;
 mod terrain_painter;
 mod terrain_render;
+mod terrain_simulation;
+mod terrain_streaming;
 mod terrain_sync;
 pub mod ui;
 
@@ -14,6 +17,9 @@ pub use box2d_visualizer::*;
 pub use camera_control::*;
 pub use render::*;
 pub use terrain_collisions::*;
+pub use terrain_fragments::*;
 pub use terrain_painter::*;
 pub use terrain_render::*;
+pub use terrain_simulation::*;
+pub use terrain_streaming::*;
 pub use terrain_sync::*;
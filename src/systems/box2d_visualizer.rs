@@ -9,19 +9,42 @@ use specs::{Read, System, Write};
 
 use crate::{
     gl::renderer::{self, UnsafeCanvas},
-    resources::{Camera, UnsafeBox2D},
+    resources::{Action, Camera, Input, UnsafeBox2D},
     util::{box2d::b2vec_to_vector2f, Vector2I},
 };
 
-pub struct Box2DVisualizer;
+pub struct Box2DVisualizer {
+    enabled: bool,
+}
+
+impl Box2DVisualizer {
+    pub fn new() -> Box2DVisualizer {
+        Box2DVisualizer { enabled: true }
+    }
+}
+
+impl Default for Box2DVisualizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> System<'a> for Box2DVisualizer {
     type SystemData = (
+        Read<'a, Input>,
         Read<'a, Camera>,
         Read<'a, UnsafeBox2D>,
         Option<Write<'a, UnsafeCanvas>>,
     );
 
-    fn run(&mut self, (camera, box2d, canvas): Self::SystemData) {
+    fn run(&mut self, (input, camera, box2d, canvas): Self::SystemData) {
+        if input.action_pressed(Action::ToggleDebug) {
+            self.enabled = !self.enabled;
+        }
+        if !self.enabled {
+            return;
+        }
+
         let mut canvas = match canvas {
             Some(canvas) => canvas,
             None => return,
@@ -1,7 +1,7 @@
 use std::f32::consts::PI;
 
 use crate::{
-    resources::{Camera, Input, MouseButton, Time},
+    resources::{Action, Camera, Input, Time},
     util::{math::lerp, Vector2F, Vector2I},
 };
 use specs::{Read, System, Write};
@@ -11,6 +11,9 @@ pub struct CameraControl {
 }
 
 impl CameraControl {
+    /// Pixels per second the camera moves with the pan actions fully pushed
+    pub const PAN_SPEED: f32 = 600.0;
+
     pub fn new() -> CameraControl {
         CameraControl { drag_start: None }
     }
@@ -20,24 +23,37 @@ impl<'a> System<'a> for CameraControl {
     type SystemData = (Read<'a, Time>, Read<'a, Input>, Write<'a, Camera>);
 
     fn run(&mut self, (time, input, mut camera): Self::SystemData) {
-        if input.mouse_pressed(MouseButton::Middle) {
+        if input.action_pressed(Action::Pan) {
             self.drag_start =
                 Some(camera.transform.get_position().rounded() + input.get_mouse_position());
         }
-        if input.mouse_released(MouseButton::Middle) {
+        if input.action_released(Action::Pan) {
             self.drag_start = None;
         }
 
-        if input.mouse_held(MouseButton::Middle) && self.drag_start.is_some() {
+        if input.action_held(Action::Pan) && self.drag_start.is_some() {
             camera.transform.set_position(Vector2F::from(
                 self.drag_start.unwrap() - input.get_mouse_position(),
             ));
         }
 
+        let pan = Vector2F {
+            x: input.action_axis(Action::PanLeft, Action::PanRight),
+            y: input.action_axis(Action::PanUp, Action::PanDown),
+        };
+        if pan != Vector2F::ZERO && self.drag_start.is_none() {
+            let position = camera.transform.get_position();
+            camera
+                .transform
+                .set_position(position + pan * Self::PAN_SPEED * time.delta_time.as_secs_f32());
+        }
+
+        let zoom = input.get_mouse_scroll().y + input.action_pressed(Action::ZoomIn) as i32
+            - input.action_pressed(Action::ZoomOut) as i32;
         let scale = camera.transform.get_scale();
         camera
             .transform
-            .set_scale(Vector2F::ONE * (scale.x + input.get_mouse_scroll().y as f32 * 0.1));
+            .set_scale(Vector2F::ONE * (scale.x + zoom as f32 * 0.1));
         // if input.get_mouse_scroll().y == 0 {
         //     println!("Scale: {}", camera.transform.get_scale());
         // }
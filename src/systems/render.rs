@@ -1,6 +1,9 @@
 use crate::{
     components::{ui::ElementShadow, RenderTarget, Transform},
-    gl::renderer::{self, UnsafeCanvas},
+    gl::{
+        renderer::{self, UnsafeCanvas},
+        texture_cache::UnsafeTextureCache,
+    },
     resources::Camera,
     util::Vector2F,
 };
@@ -8,31 +11,43 @@ use sdl2::{
     pixels::Color,
     rect::{Point, Rect},
 };
-use specs::{rayon::slice::ParallelSliceMut, Join, Read, ReadStorage, System, Write};
+use specs::{
+    rayon::slice::ParallelSliceMut, Entities, Entity, Join, Read, ReadStorage, System, Write,
+    WriteStorage,
+};
 
 pub struct Render;
 impl<'a> System<'a> for Render {
     type SystemData = (
+        Entities<'a>,
         ReadStorage<'a, Transform>,
-        ReadStorage<'a, RenderTarget<'static>>,
+        WriteStorage<'a, RenderTarget<'static>>,
         ReadStorage<'a, ElementShadow>,
         Read<'a, Camera>,
         Option<Write<'a, UnsafeCanvas>>,
+        Option<Write<'a, UnsafeTextureCache>>,
     );
 
-    fn run(&mut self, (transform, render_target, shadow, camera, canvas): Self::SystemData) {
-        let mut canvas = match canvas {
-            Some(canvas) => canvas,
-            None => return,
+    fn run(
+        &mut self,
+        (entities, transform, mut render_target, shadow, camera, canvas, texture_cache): Self::SystemData,
+    ) {
+        let (mut canvas, mut texture_cache) = match (canvas, texture_cache) {
+            (Some(canvas), Some(texture_cache)) => (canvas, texture_cache),
+            _ => return,
         };
 
-        let mut surfaces: Vec<(&Transform, &RenderTarget, Option<&ElementShadow>)> =
-            (&transform, &render_target, (&shadow).maybe())
-                .join()
-                .collect();
-        surfaces.par_sort_by(|a, b| a.1.sorting_order.cmp(&b.1.sorting_order));
+        let mut surfaces: Vec<(
+            Entity,
+            &Transform,
+            &mut RenderTarget,
+            Option<&ElementShadow>,
+        )> = (&entities, &transform, &mut render_target, (&shadow).maybe())
+            .join()
+            .collect();
+        surfaces.par_sort_by(|a, b| a.2.sorting_order.cmp(&b.2.sorting_order));
 
-        for (transform, render_target, shadow) in surfaces {
+        for (entity, transform, render_target, shadow) in surfaces {
             let cam_transform = if render_target.use_screen_space {
                 Transform::IDENTITY.with_scale(Vector2F::ONE)
             } else {
@@ -62,6 +77,7 @@ impl<'a> System<'a> for Render {
                 (dst_end.x - dst_start.x) as u32,
                 (dst_end.y - dst_start.y) as u32,
             );
+            let texture = texture_cache.get(&canvas, entity, render_target);
 
             match shadow {
                 Some(shadow) => {
@@ -79,9 +95,9 @@ impl<'a> System<'a> for Render {
                         (dst_end.x - dst_start.x) as u32,
                         (dst_end.y - dst_start.y) as u32,
                     );
-                    renderer::draw_surface_rotated(
+                    renderer::draw_texture_rotated(
                         &mut canvas,
-                        &render_target.surface,
+                        texture,
                         shadow.color,
                         src,
                         dst,
@@ -100,9 +116,9 @@ impl<'a> System<'a> for Render {
             }
 
             // FIXME: Camera rotation is broken
-            renderer::draw_surface_rotated(
+            renderer::draw_texture_rotated(
                 &mut canvas,
-                &render_target.surface,
+                texture,
                 Color::WHITE,
                 src,
                 dst,
@@ -115,5 +131,8 @@ impl<'a> System<'a> for Render {
                 transform.get_scale().y < 0.0,
             );
         }
+
+        // Free the textures of render targets that are gone
+        texture_cache.retain(|entity| entities.is_alive(entity) && render_target.contains(entity));
     }
 }
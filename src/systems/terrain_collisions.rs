@@ -1,47 +1,172 @@
+use std::collections::HashMap;
+
 use crate::{
-    components::{ChunkIndex, PhysicsBody, Transform},
-    mst::marching_square,
-    resources::{Terrain, TerrainUpdate, UnsafeBox2D},
+    components::{ChunkIndex, PhysicsBody},
+    mst::{
+        chunk::Chunk,
+        marching_square::{ChunkContours, CollisionOptions, Outline},
+    },
+    resources::{Materials, Terrain, TerrainUpdate, UnsafeBox2D, UserData},
     util::{
-        box2d::{create_segmented_shape, replace_shape},
-        Listener,
+        box2d::{
+            add_chain_fixture, create_chain_shape, create_segmented_shape, destroy_body,
+            remove_fixture, replace_shape,
+        },
+        Listener, Vector2I,
     },
 };
-use box2d_rs::shapes::b2_chain_shape::B2chainShape;
-use specs::{Join, Read, ReadStorage, System, Write, WriteStorage};
+use box2d_rs::{b2_fixture::FixturePtr, shapes::b2_chain_shape::B2chainShape};
+use specs::{rayon::prelude::*, Entities, Entity, Join, Read, System, Write, WriteStorage};
 
 pub struct TerrainCollision {
     terrain_listener: Option<Listener>,
+    options: CollisionOptions,
+    /// Current outlines of every chunk
+    contours: HashMap<Vector2I, ChunkContours>,
+    /// Fixture of each outline in `contours`
+    fixtures: HashMap<Vector2I, Vec<FixturePtr<UserData>>>,
+    /// Dirty chunks that don't have an entity yet, meshed once they do
+    deferred: HashMap<Vector2I, Option<Vec<Vector2I>>>,
 }
 
 impl TerrainCollision {
+    pub const SIMPLIFY_TOLERANCE: f32 = 1.0;
+    /// Texels this close to the chunk edge affect the outlines of the neighbouring chunks
+    const BORDER_WIDTH: i32 = 2;
+
     pub fn new() -> TerrainCollision {
+        Self::with_options(CollisionOptions {
+            simplify_tolerance: Some(Self::SIMPLIFY_TOLERANCE),
+            ..CollisionOptions::default()
+        })
+    }
+
+    pub fn with_options(options: CollisionOptions) -> TerrainCollision {
         TerrainCollision {
             terrain_listener: None,
+            options,
+            contours: HashMap::new(),
+            fixtures: HashMap::new(),
+            deferred: HashMap::new(),
+        }
+    }
+
+    pub fn options(&self) -> CollisionOptions {
+        self.options
+    }
+
+    fn create_shape(outline: Outline) -> B2chainShape {
+        if outline.closed {
+            return create_segmented_shape(outline.points);
+        }
+        let (prev, next) = match outline.ghost_vertices {
+            Some(ghost_vertices) => ghost_vertices,
+            None => {
+                // Extend the end sides
+                let len = outline.points.len();
+                (
+                    outline.points[0] * 2.0 - outline.points[1],
+                    outline.points[len - 1] * 2.0 - outline.points[len - 2],
+                )
+            }
+        };
+        create_chain_shape(outline.points, prev, next)
+    }
+
+    /// Bring the fixtures of a chunk up to date with its contours. With `kept` (see `ChunkContours::update`),
+    /// only the fixtures of the outlines that were re-traced are replaced, otherwise every fixture is.
+    fn update_shapes(
+        &mut self,
+        index: &Vector2I,
+        physics_body: &PhysicsBody,
+        kept: Option<Vec<bool>>,
+        materials: &Materials,
+    ) {
+        let fixtures = self.fixtures.entry(*index).or_default();
+        let kept = match kept {
+            Some(kept) => kept,
+            None => {
+                // Also clears fixtures of a body that was created for a reloaded chunk
+                replace_shape(physics_body.body.clone(), Vec::new(), Vec::new());
+                fixtures.clear();
+                Vec::new()
+            }
+        };
+        let mut remaining: Vec<FixturePtr<UserData>> = Vec::new();
+        for (fixture, keep) in fixtures.drain(..).zip(kept) {
+            match keep {
+                true => remaining.push(fixture),
+                false => remove_fixture(&physics_body.body, fixture),
+            }
+        }
+        *fixtures = remaining;
+
+        let outlines = match self.contours.get(index) {
+            Some(contours) => &contours.outlines,
+            None => return,
+        };
+        for outline in outlines[fixtures.len()..].iter() {
+            let friction = materials.friction(outline.material);
+            fixtures.push(add_chain_fixture(
+                &physics_body.body,
+                Self::create_shape(outline.clone()),
+                friction,
+            ));
         }
     }
+
+    /// Chunk indices whose outlines depend on the texel at `local` in chunk `index`
+    fn affected_chunks(index: &Vector2I, local: &Vector2I) -> Vec<Vector2I> {
+        let offsets = |position: i32, size: i32| -> Vec<i32> {
+            let mut offsets = vec![0];
+            if position < Self::BORDER_WIDTH {
+                offsets.push(-1);
+            }
+            if position >= size - Self::BORDER_WIDTH {
+                offsets.push(1);
+            }
+            offsets
+        };
+        let mut result: Vec<Vector2I> = Vec::new();
+        for y in offsets(local.y, Chunk::SIZE.y) {
+            for x in offsets(local.x, Chunk::SIZE.x) {
+                result.push(*index + Vector2I { x, y });
+            }
+        }
+        result
+    }
+}
+
+impl Default for TerrainCollision {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> System<'a> for TerrainCollision {
     type SystemData = (
-        ReadStorage<'a, Transform>,
-        ReadStorage<'a, ChunkIndex>,
+        Entities<'a>,
+        WriteStorage<'a, ChunkIndex>,
         WriteStorage<'a, PhysicsBody>,
         Read<'a, UnsafeBox2D>,
+        Read<'a, Materials>,
         Write<'a, Terrain>,
     );
 
-    fn run(&mut self, (transform, chunk, mut physics_body, box2d, mut terrain): Self::SystemData) {
+    fn run(
+        &mut self,
+        (entities, chunk_index, mut physics_body, box2d, materials, mut terrain): Self::SystemData,
+    ) {
+        // Outlines continue across chunk borders, so neighbouring chunks are re-meshed together.
+        // Chunks map to the changed positions in their local space, or `None` to re-mesh all of it.
+        let mut dirty: HashMap<Vector2I, Option<Vec<Vector2I>>> =
+            std::mem::take(&mut self.deferred);
         let events = match self.terrain_listener {
             Some(listener) => terrain.consume_changes(listener),
             None => {
                 // Initialize
-                for (chunk, physics_body) in (&chunk, &mut physics_body).join() {
-                    let chunk = match terrain.index_to_chunk(&chunk.index) {
-                        Some(chunk) => chunk,
-                        None => continue,
-                    };
-                    // TODO: reduce duplicate code
+                for chunk_index in (&chunk_index).join() {
+                    dirty.insert(chunk_index.index, None);
                 }
                 None
             }
@@ -52,40 +177,49 @@ impl<'a> System<'a> for TerrainCollision {
                 for event in events {
                     match event {
                         TerrainUpdate::ChunkAdded(index) => {
-                            let (_, physics_body) = match (&chunk, &mut physics_body)
-                                .join()
-                                .find(|(chunk, _)| chunk.index == index)
-                            {
-                                Some(value) => value,
-                                None => panic!("Could not find chunk entity for update"),
-                            };
-                            let chunk = match terrain.index_to_chunk(&index) {
-                                Some(chunk) => chunk,
-                                None => continue,
-                            };
-                            // TODO: reduce duplicate code
+                            for y in -1..=1 {
+                                for x in -1..=1 {
+                                    dirty.insert(index + Vector2I { x, y }, None);
+                                }
+                            }
                         }
-                        TerrainUpdate::ChunkRemoved(index) => {}
                         TerrainUpdate::TexelsUpdated(index, changes) => {
-                            let (_, physics_body) = match (&chunk, &mut physics_body)
+                            for change in changes {
+                                for affected in Self::affected_chunks(&index, &change.position) {
+                                    let position =
+                                        change.position + (index - affected) * Chunk::SIZE;
+                                    match dirty.entry(affected).or_insert(Some(Vec::new())) {
+                                        Some(positions) => positions.push(position),
+                                        None => (),
+                                    }
+                                }
+                            }
+                        }
+                        TerrainUpdate::ChunkRemoved(index) => {
+                            // The chunk might have been loaded again within the same frame
+                            if terrain.is_chunk_loaded(&index) {
+                                continue;
+                            }
+                            let removed: Vec<Entity> = (&entities, &chunk_index, &physics_body)
                                 .join()
-                                .find(|(chunk, _)| chunk.index == index)
-                            {
-                                Some(value) => value,
-                                None => panic!("Could not find chunk entity for update"),
-                            };
-                            let chunk = match terrain.index_to_chunk(&index) {
-                                Some(chunk) => chunk,
-                                None => continue,
-                            };
-                            // TODO: reduce duplicate code
-                            let islands = marching_square::calculate_collisions(chunk);
-                            let mut shapes: Vec<B2chainShape> = Vec::with_capacity(islands.len());
-                            for island in islands {
-                                shapes.push(create_segmented_shape(island))
+                                .filter(|(_, chunk_index, _)| chunk_index.index == index)
+                                .map(|(entity, _, _)| entity)
+                                .collect();
+                            for entity in removed {
+                                match physics_body.remove(entity) {
+                                    Some(physics_body) => {
+                                        destroy_body(box2d.world_ptr.clone(), physics_body.body)
+                                    }
+                                    None => (),
+                                }
+                            }
+                            self.contours.remove(&index);
+                            self.fixtures.remove(&index);
+                            for y in -1..=1 {
+                                for x in -1..=1 {
+                                    dirty.insert(index + Vector2I { x, y }, None);
+                                }
                             }
-
-                            replace_shape(physics_body.body.clone(), vec![], shapes);
                         }
                         TerrainUpdate::None => (),
                     }
@@ -94,6 +228,126 @@ impl<'a> System<'a> for TerrainCollision {
             None => (),
         };
 
+        // Chunks whose entity or body isn't created yet are kept for the next frame
+        let (mut dirty, deferred): (Vec<_>, Vec<_>) = dirty
+            .into_iter()
+            .filter(|(index, _)| terrain.is_chunk_loaded(index))
+            .partition(|(index, _)| {
+                (&chunk_index, &physics_body)
+                    .join()
+                    .any(|(chunk_index, _)| chunk_index.index == *index)
+            });
+        self.deferred.extend(deferred);
+        // Sorted so that the shapes are created the same way every run
+        dirty.sort_by_key(|(index, _)| (index.y, index.x));
+
+        // Only the Box2D fixtures have to be created on this thread, the outlines are traced in parallel.
+        // Chunks that were meshed before only re-trace the outlines around the changes.
+        let jobs: Vec<(Vector2I, Option<Vec<Vector2I>>, Option<ChunkContours>)> = dirty
+            .into_iter()
+            .map(|(index, changed)| {
+                let contours = match changed {
+                    Some(_) => self.contours.remove(&index),
+                    None => None,
+                };
+                (index, changed, contours)
+            })
+            .collect();
+        let (options, terrain_ref) = (&self.options, &*terrain);
+        let traced: Vec<(Vector2I, ChunkContours, Option<Vec<bool>>)> = jobs
+            .into_par_iter()
+            .map(|(index, changed, contours)| match (changed, contours) {
+                (Some(changed), Some(mut contours)) => {
+                    let kept = contours.update(terrain_ref, &index, &materials, options, &changed);
+                    (index, contours, Some(kept))
+                }
+                _ => (
+                    index,
+                    ChunkContours::new(terrain_ref, &index, &materials, options),
+                    None,
+                ),
+            })
+            .collect();
+        for (index, contours, kept) in traced {
+            let (_, physics_body) = match (&chunk_index, &physics_body)
+                .join()
+                .find(|(chunk_index, _)| chunk_index.index == index)
+            {
+                Some(value) => value,
+                None => continue,
+            };
+            self.contours.insert(index, contours);
+            self.update_shapes(&index, physics_body, kept, &materials);
+        }
+
         self.terrain_listener = Some(terrain.get_listener());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use box2d_rs::{b2_body::B2body, b2_fixture::FixturePtr};
+    use specs::{Join, WorldExt};
+
+    use crate::{
+        components::{ChunkIndex, PhysicsBody},
+        mst::chunk::Chunk,
+        resources::{InputState, Materials, Terrain, UserData},
+        simulation::{Simulation, SimulationOptions},
+        util::Vector2I,
+    };
+
+    fn fixtures(simulation: &Simulation) -> Vec<FixturePtr<UserData>> {
+        let chunk_index = simulation.world.read_storage::<ChunkIndex>();
+        let physics_body = simulation.world.read_storage::<PhysicsBody>();
+        let (_, physics_body) = (&chunk_index, &physics_body)
+            .join()
+            .find(|(chunk_index, _)| chunk_index.index == Vector2I::ZERO)
+            .unwrap();
+        let fixtures = B2body::get_fixture_list(&RefCell::borrow(&physics_body.body))
+            .iter()
+            .collect();
+        fixtures
+    }
+
+    #[test]
+    fn remesh_edited_outline() {
+        let mut chunk = Chunk::new();
+        let bedrock = Materials::default().find("bedrock").unwrap();
+        for y in 4..8 {
+            for x in 4..8 {
+                chunk.set_texel(&Vector2I { x, y }, bedrock);
+                chunk.set_texel(
+                    &Vector2I {
+                        x: x + 16,
+                        y: y + 16,
+                    },
+                    bedrock,
+                );
+            }
+        }
+        let terrain = Terrain::from_chunks(HashMap::from([(Vector2I::ZERO, chunk)]));
+        let mut simulation =
+            Simulation::new(terrain, Materials::default(), SimulationOptions::default());
+        simulation.step(InputState::default());
+        let before = fixtures(&simulation);
+        assert_eq!(before.len(), 2);
+
+        // Grow the first blob
+        simulation
+            .world
+            .write_resource::<Terrain>()
+            .set_texel(&Vector2I { x: 8, y: 4 }, bedrock);
+        simulation.step(InputState::default());
+        let after = fixtures(&simulation);
+        assert_eq!(after.len(), 2);
+        // Only the edited outline got a new fixture
+        let kept = after
+            .iter()
+            .filter(|fixture| before.iter().any(|old| Rc::ptr_eq(old, fixture)))
+            .count();
+        assert_eq!(kept, 1);
+    }
+}
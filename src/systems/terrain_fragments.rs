@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+
+use box2d_rs::b2_body::{B2body, B2bodyType};
+
+use crate::{
+    components::{PhysicsBody, RenderTarget, Transform},
+    gl::renderer::SURFACE_FORMAT_BPP,
+    mst::{
+        connectivity::{find_fragments, Fragment},
+        marching_square::{self, Boundary, CollisionOptions},
+        texel::Texel,
+        utils::index_to_global,
+    },
+    resources::{Materials, Terrain, TerrainUpdate, UnsafeBox2D},
+    systems::TerrainCollision,
+    util::{
+        box2d::{create_body, create_solid_shape},
+        Listener, SortingOrder, Vector2F, Vector2I,
+    },
+};
+use specs::{Entities, Read, System, Write, WriteStorage};
+
+/// Breaks off terrain that is no longer connected to anything after edits, and turns it into dynamic bodies
+pub struct TerrainFragments {
+    terrain_listener: Option<Listener>,
+}
+
+impl TerrainFragments {
+    pub fn new() -> TerrainFragments {
+        TerrainFragments {
+            terrain_listener: None,
+        }
+    }
+
+    fn create_render_target(fragment: &Fragment, materials: &Materials) -> RenderTarget<'static> {
+        let size = fragment.size();
+        let mut render_target = RenderTarget::new(
+            size.x as u32,
+            size.y as u32,
+            Vector2F::ONE * 0.5,
+            SortingOrder::Default as i16,
+            false,
+        );
+        let pitch = render_target.surface.pitch() as usize;
+        render_target.surface.with_lock_mut(|p_data| {
+            for (global, id) in fragment.texels.iter() {
+                let local = *global - fragment.min;
+                let i = local.y as usize * pitch + local.x as usize * SURFACE_FORMAT_BPP;
+                let (r, g, b, a) = materials.color_at(*id, global);
+                p_data[i + 0] = r;
+                p_data[i + 1] = g;
+                p_data[i + 2] = b;
+                p_data[i + 3] = a;
+            }
+        });
+        render_target
+    }
+
+    /// Simplified outline of the fragment relative to its center. Holes are filled.
+    fn create_outline(
+        fragment: &Fragment,
+        materials: &Materials,
+        center: Vector2F,
+    ) -> Vec<Vector2F> {
+        let (grid, size) = (fragment.to_grid(), fragment.grid_size());
+        let sample = |local: Vector2I| Some(grid[(local.y * size.x + local.x) as usize]);
+        let islands = marching_square::calculate_area_collisions(
+            &sample,
+            size,
+            materials,
+            &CollisionOptions {
+                simplify_tolerance: Some(TerrainCollision::SIMPLIFY_TOLERANCE),
+                ..CollisionOptions::default()
+            },
+        );
+        let island = match islands
+            .into_iter()
+            .filter(|island| island.boundary == Boundary::Outer)
+            .max_by(|a, b| a.area.total_cmp(&b.area))
+        {
+            Some(island) => island,
+            None => return vec![],
+        };
+        // The grid used for meshing has a border of one texel
+        let offset = Vector2F::from(fragment.min - Vector2I::ONE) - center;
+        island.points.iter().map(|point| *point + offset).collect()
+    }
+}
+
+impl Default for TerrainFragments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> System<'a> for TerrainFragments {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, PhysicsBody>,
+        WriteStorage<'a, RenderTarget<'static>>,
+        Read<'a, UnsafeBox2D>,
+        Read<'a, Materials>,
+        Write<'a, Terrain>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut transform,
+            mut physics_body,
+            mut render_target,
+            box2d,
+            materials,
+            mut terrain,
+        ): Self::SystemData,
+    ) {
+        let events = match self.terrain_listener {
+            Some(listener) => terrain.consume_changes(listener),
+            None => None,
+        };
+
+        // Terrain next to removed texels might have lost its support
+        let mut seeds: Vec<Vector2I> = Vec::new();
+        match events {
+            Some(events) => {
+                for event in events {
+                    match event {
+                        TerrainUpdate::TexelsUpdated(index, changes) => {
+                            let origin = index_to_global(&index);
+                            for change in changes {
+                                if materials.is_rigid(change.id) {
+                                    continue;
+                                }
+                                for offset in Texel::NEIGHBOUR_OFFSET_VECTORS {
+                                    seeds.push(origin + change.position + offset);
+                                }
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            None => (),
+        };
+
+        for fragment in find_fragments(&terrain, &materials, &seeds) {
+            let center = Vector2F::from(fragment.min) + Vector2F::from(fragment.size()) / 2.0;
+            let outline = Self::create_outline(&fragment, &materials, center);
+            // Leave the terrain alone if there's no body to replace it with
+            if outline.len() < 3 {
+                continue;
+            }
+            let shapes = match create_solid_shape(outline) {
+                Some(shapes) => shapes,
+                None => continue,
+            };
+            for (global, _) in fragment.texels.iter() {
+                terrain.set_texel(global, Texel::EMPTY);
+            }
+
+            let body = create_body(
+                box2d.world_ptr.clone(),
+                Some(B2bodyType::B2DynamicBody),
+                shapes,
+                vec![],
+                Some(center),
+                Some(0.0),
+            );
+            // Average friction of the materials the fragment is made of
+            let friction = fragment
+                .texels
+                .iter()
+                .map(|(_, id)| materials.friction(*id))
+                .sum::<f32>()
+                / fragment.texels.len() as f32;
+            for fixture in B2body::get_fixture_list(&RefCell::borrow(&body)).iter() {
+                fixture.borrow_mut().set_friction(friction);
+            }
+            entities
+                .build_entity()
+                .with(Transform::IDENTITY.with_position(center), &mut transform)
+                .with(PhysicsBody::new(body), &mut physics_body)
+                .with(
+                    Self::create_render_target(&fragment, &materials),
+                    &mut render_target,
+                )
+                .build();
+        }
+
+        self.terrain_listener = Some(terrain.get_listener());
+    }
+}
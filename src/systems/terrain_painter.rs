@@ -1,67 +1,193 @@
 use crate::{
-    mst::texel::TexelID,
-    resources::{Camera, Input, MouseButton, Terrain},
+    mst::{
+        brush::{self, BrushShape, Selection, Tool},
+        texel::TexelID,
+    },
+    resources::{Action, Camera, EditHistory, Input, Materials, Terrain, Time},
     util::Vector2I,
 };
 use specs::{Read, System, Write};
 
 pub struct TerrainPainter {
     radius: i32,
+    tool: Tool,
+    /// Material painted with `Action::Paint`
+    material: TexelID,
+    /// Where the current stroke started, and where the brush was last frame
+    stroke: Option<(Vector2I, Vector2I)>,
+    selection: Option<(Vector2I, Vector2I)>,
+    clipboard: Option<Selection>,
 }
 
 impl TerrainPainter {
     pub fn new() -> TerrainPainter {
-        TerrainPainter { radius: 6 }
+        TerrainPainter {
+            radius: 6,
+            tool: Tool::Brush(BrushShape::Circle),
+            material: 1,
+            stroke: None,
+            selection: None,
+            clipboard: None,
+        }
     }
-}
 
-impl<'a> TerrainPainter {
-    fn paint_circle(
-        &self,
-        terrain: &mut Write<'a, Terrain>,
-        origin: Vector2I,
-        radius: i32,
-        id: TexelID,
-    ) {
-        for y in origin.y - (radius - 1)..origin.y + radius {
-            for x in origin.x - (radius - 1)..origin.x + radius {
-                let dx = (x - origin.x).abs();
-                let dy = (y - origin.y).abs();
-                if dx * dx + dy * dy <= (radius - 1) * (radius - 1) {
-                    terrain.set_texel(&Vector2I { x, y }, id)
-                }
-            }
-        }
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    pub fn material(&self) -> TexelID {
+        self.material
     }
 
     fn mouse_to_world_pos(camera: &Camera, mouse_position: Vector2I) -> Vector2I {
         (camera.transform.get_position().rounded() + mouse_position) / 4 // FIXME: harcoded value
     }
+
+    fn select_tool(&mut self, input: &Input) {
+        let tools = [
+            (Action::ToolCircle, Tool::Brush(BrushShape::Circle)),
+            (Action::ToolSquare, Tool::Brush(BrushShape::Square)),
+            (Action::ToolLine, Tool::Line),
+            (Action::ToolFill, Tool::Fill),
+            (Action::ToolSpray, Tool::Spray),
+            (Action::ToolDig, Tool::Dig),
+            (Action::ToolSelect, Tool::Select),
+        ];
+        for (action, tool) in tools {
+            if input.action_pressed(action) && self.stroke.is_none() {
+                self.tool = tool;
+            }
+        }
+    }
+
+    /// Cycle through the materials, skipping air
+    fn select_material(&mut self, input: &Input, materials: &Materials) {
+        let ids: Vec<TexelID> = materials
+            .iter()
+            .map(|(id, _)| id)
+            .filter(|id| *id != 0)
+            .collect();
+        if ids.is_empty() {
+            return;
+        }
+        let current = ids.iter().position(|id| *id == self.material).unwrap_or(0);
+        if input.action_pressed(Action::NextMaterial) {
+            self.material = ids[(current + 1) % ids.len()];
+        }
+        if input.action_pressed(Action::PreviousMaterial) {
+            self.material = ids[(current + ids.len() - 1) % ids.len()];
+        }
+    }
+
+    /// Use the tool with the id of the held action
+    fn apply(
+        &mut self,
+        terrain: &mut Terrain,
+        materials: &Materials,
+        input: &Input,
+        brush_pos: Vector2I,
+        seed: u64,
+    ) {
+        let held = [
+            (Action::Paint, Some(self.material)),
+            (Action::Erase, Some(0)),
+            (Action::PaintSand, materials.find("sand")),
+        ]
+        .into_iter()
+        .find(|(action, _)| input.action_held(*action) || input.action_released(*action));
+        let (action, id) = match held {
+            Some((action, Some(id))) => (action, id),
+            _ => {
+                self.stroke = None;
+                return;
+            }
+        };
+        let pressed = input.action_pressed(action);
+        let released = input.action_released(action);
+        let (start, last) = match self.stroke {
+            Some(stroke) if !pressed => stroke,
+            _ => (brush_pos, brush_pos),
+        };
+
+        match self.tool {
+            Tool::Brush(shape) => {
+                brush::paint_line(terrain, shape, last, brush_pos, self.radius, id)
+            }
+            Tool::Line if released => brush::paint_line(
+                terrain,
+                BrushShape::Circle,
+                start,
+                brush_pos,
+                self.radius,
+                id,
+            ),
+            Tool::Fill if pressed => {
+                brush::flood_fill(terrain, brush_pos, id);
+            }
+            Tool::Spray => brush::spray(terrain, brush_pos, self.radius, id, seed),
+            Tool::Dig => brush::dig(
+                terrain,
+                brush_pos,
+                self.radius,
+                &brush::diggable_materials(materials),
+                seed,
+            ),
+            Tool::Select => self.selection = Some((start, brush_pos)),
+            _ => (),
+        }
+
+        self.stroke = match released {
+            true => None,
+            false => Some((start, brush_pos)),
+        };
+    }
 }
 
 impl<'a> System<'a> for TerrainPainter {
-    type SystemData = (Read<'a, Input>, Read<'a, Camera>, Write<'a, Terrain>);
-
-    fn run(&mut self, (input, camera, mut terrain): Self::SystemData) {
-        let mut updates: Vec<(Vector2I, TexelID)> = Vec::new();
+    type SystemData = (
+        Read<'a, Input>,
+        Read<'a, Camera>,
+        Read<'a, Materials>,
+        Read<'a, Time>,
+        Write<'a, Terrain>,
+        Write<'a, EditHistory>,
+    );
 
+    fn run(
+        &mut self,
+        (input, camera, materials, time, mut terrain, mut history): Self::SystemData,
+    ) {
         self.radius = (self.radius + input.get_mouse_scroll().y).clamp(1, 128);
+        self.select_tool(&input);
+        self.select_material(&input, &materials);
 
-        // TODO: Fix scaled transforms, remove hardcoded values
-        let brush_pos = Self::mouse_to_world_pos(&camera, input.get_mouse_position());
-        if input.mouse_held(MouseButton::Left) {
-            self.paint_circle(&mut terrain, brush_pos, self.radius, 1)
+        if self.stroke.is_none() && input.action_pressed(Action::Undo) {
+            history.undo(&mut terrain);
         }
-
-        if input.mouse_held(MouseButton::Right) {
-            self.paint_circle(&mut terrain, brush_pos, self.radius, 0)
+        if self.stroke.is_none() && input.action_pressed(Action::Redo) {
+            history.redo(&mut terrain);
         }
 
-        loop {
-            match updates.pop() {
-                Some((global, id)) => terrain.set_texel(&global, id),
-                None => break,
+        // Edits are grouped into one undo step until the stroke ends
+        terrain.start_recording();
+
+        // TODO: Fix scaled transforms, remove hardcoded values
+        let brush_pos = Self::mouse_to_world_pos(&camera, input.get_cursor_position());
+        self.apply(&mut terrain, &materials, &input, brush_pos, time.frame);
+
+        if input.action_pressed(Action::Copy) {
+            self.clipboard = self.selection.map(|(a, b)| Selection::copy(&terrain, a, b));
+        }
+        if input.action_pressed(Action::Paste) {
+            match &self.clipboard {
+                Some(clipboard) => clipboard.paste(&mut terrain, brush_pos),
+                None => (),
             }
         }
+
+        history.record(terrain.stop_recording());
+        if self.stroke.is_none() {
+            history.end_stroke();
+        }
     }
 }
@@ -1,25 +1,78 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     components::{ChunkIndex, RenderTarget},
-    gl::renderer::SURFACE_FORMAT_BPP,
-    mst::texel::TexelID,
-    resources::{Terrain, TerrainUpdate},
-    util::Listener,
+    mst::{
+        chunk::Chunk,
+        utils::{index_to_global, local_to_texel_index, texel_index_to_local},
+    },
+    resources::{MaterialColor, Materials, Terrain, TerrainUpdate},
+    util::{Listener, Vector2I},
 };
 use sdl2::pixels::Color;
-use specs::{Join, ReadStorage, System, Write, WriteStorage};
+use specs::{
+    rayon::prelude::*, Entities, Entity, Join, Read, ReadStorage, System, Write, WriteStorage,
+};
+
+/// Pixels of a chunk surface that need to be repainted
+enum Repaint {
+    All,
+    Texels(HashSet<Vector2I>),
+}
 
 pub struct TerrainRender {
     terrain_listener: Option<Listener>,
+    /// Repaints of chunks that don't have an entity yet, painted once they do
+    deferred: HashMap<Vector2I, Repaint>,
 }
 
 impl TerrainRender {
     pub fn new() -> TerrainRender {
         TerrainRender {
             terrain_listener: None,
+            deferred: HashMap::new(),
         }
     }
+
+    /// Colours of the repainted texels, doesn't touch the surface so chunks can be coloured in parallel
+    fn colors(
+        chunk: &Chunk,
+        index: &Vector2I,
+        materials: &Materials,
+        repaint: &Repaint,
+    ) -> Vec<(Vector2I, MaterialColor)> {
+        let origin = index_to_global(index);
+        let positions: Vec<Vector2I> = match repaint {
+            Repaint::All => (0..chunk.texels.len()).map(texel_index_to_local).collect(),
+            Repaint::Texels(positions) => positions.iter().copied().collect(),
+        };
+        positions
+            .into_iter()
+            .filter_map(|local| {
+                local_to_texel_index(&local).map(|i| {
+                    let texel = chunk.texels[i];
+                    (local, materials.color_at(texel.id, &(origin + local)))
+                })
+            })
+            .collect()
+    }
+
+    fn paint(render_target: &mut RenderTarget<'static>, colors: &[(Vector2I, MaterialColor)]) {
+        let pitch = render_target.surface.pitch() as usize;
+        let format = render_target.surface.pixel_format();
+        let bpp = render_target
+            .surface
+            .pixel_format_enum()
+            .byte_size_per_pixel();
+        render_target.surface.with_lock_mut(|p_data| {
+            for (local, (r, g, b, a)) in colors.iter() {
+                let pixel = Color::RGBA(*r, *g, *b, *a).to_u32(&format).to_ne_bytes();
+                let i = local.y as usize * pitch + local.x as usize * bpp;
+                p_data[i..i + bpp].copy_from_slice(&pixel[..bpp]);
+            }
+        });
+        render_target.mark_dirty();
+    }
 }
 
 // TODO: Find out why updating chunks cause permanent fps drops, or if terrain renderer is even the problem.
@@ -27,42 +80,25 @@ impl TerrainRender {
 // The fps seems stable if terrain render is disabled
 impl<'a> System<'a> for TerrainRender {
     type SystemData = (
+        Entities<'a>,
         ReadStorage<'a, ChunkIndex>,
         WriteStorage<'a, RenderTarget<'static>>,
+        Read<'a, Materials>,
         Write<'a, Terrain>,
     );
 
-    fn run(&mut self, (chunk, mut render_target, mut terrain): Self::SystemData) {
-        let color_map: HashMap<TexelID, (u8, u8, u8, u8)> = [
-            (0, Color::RGBA(0, 0, 0, 0).rgba()),
-            (1, Color::RGBA(158, 127, 99, 255).rgba()),
-            (2, Color::RGBA(70, 142, 71, 255).rgba()),
-        ]
-        .iter()
-        .cloned()
-        .collect();
-
+    fn run(
+        &mut self,
+        (entities, chunk_index, mut render_target, materials, mut terrain): Self::SystemData,
+    ) {
+        // Coalesce the events of this frame, so every chunk is painted at most once
+        let mut repaints: HashMap<Vector2I, Repaint> = std::mem::take(&mut self.deferred);
         let events = match self.terrain_listener {
             Some(listener) => terrain.consume_changes(listener),
             None => {
                 // Initialize
-                for (chunk, render_target) in (&chunk, &mut render_target).join() {
-                    let chunk = match terrain.index_to_chunk(&chunk.index) {
-                        Some(chunk) => chunk,
-                        None => continue,
-                    };
-                    render_target.surface.with_lock_mut(|p_data| {
-                        assert!(p_data.len() == chunk.texels.len() * SURFACE_FORMAT_BPP);
-                        // FIXME: This doesn't care about bytes_per_pixel
-                        for xy in 0..chunk.texels.len() {
-                            let i = xy * SURFACE_FORMAT_BPP;
-                            let (r, g, b, a) = color_map[&chunk.texels[xy].id];
-                            p_data[i + 0] = r;
-                            p_data[i + 1] = g;
-                            p_data[i + 2] = b;
-                            p_data[i + 3] = a;
-                        }
-                    })
+                for chunk_index in (&chunk_index).join() {
+                    repaints.insert(chunk_index.index, Repaint::All);
                 }
                 None
             }
@@ -73,55 +109,34 @@ impl<'a> System<'a> for TerrainRender {
                 for event in events {
                     match event {
                         TerrainUpdate::ChunkAdded(index) => {
-                            let (_, render_target) = match (&chunk, &mut render_target)
+                            repaints.insert(index, Repaint::All);
+                        }
+                        TerrainUpdate::ChunkRemoved(index) => {
+                            // The chunk might have been loaded again within the same frame
+                            if terrain.is_chunk_loaded(&index) {
+                                continue;
+                            }
+                            repaints.remove(&index);
+                            // Free the surface right away instead of waiting for the entity to be cleaned up
+                            let removed: Vec<Entity> = (&entities, &chunk_index, &render_target)
                                 .join()
-                                .find(|(chunk, _)| chunk.index == index)
-                            {
-                                Some(value) => value,
-                                None => panic!("Could not find chunk entity for update"),
-                            };
-                            let chunk = match terrain.index_to_chunk(&index) {
-                                Some(chunk) => chunk,
-                                None => continue,
-                            };
-                            render_target.surface.with_lock_mut(|p_data| {
-                                assert!(p_data.len() == chunk.texels.len() * SURFACE_FORMAT_BPP);
-                                // FIXME: This doesn't care about bytes_per_pixel
-                                for xy in 0..chunk.texels.len() {
-                                    let i = xy * SURFACE_FORMAT_BPP;
-                                    let (r, g, b, a) = color_map[&chunk.texels[xy].id];
-                                    p_data[i + 0] = r;
-                                    p_data[i + 1] = g;
-                                    p_data[i + 2] = b;
-                                    p_data[i + 3] = a;
-                                }
-                            })
+                                .filter(|(_, chunk_index, _)| chunk_index.index == index)
+                                .map(|(entity, _, _)| entity)
+                                .collect();
+                            for entity in removed {
+                                render_target.remove(entity);
+                            }
                         }
-                        TerrainUpdate::ChunkRemoved(index) => {}
                         TerrainUpdate::TexelsUpdated(index, changes) => {
-                            let (_, render_target) = match (&chunk, &mut render_target)
-                                .join()
-                                .find(|(chunk, _)| chunk.index == index)
-                            {
-                                Some(value) => value,
-                                None => panic!("Could not find chunk entity for update"),
-                            };
-                            let chunk = match terrain.index_to_chunk(&index) {
-                                Some(chunk) => chunk,
-                                None => continue,
-                            };
-                            render_target.surface.with_lock_mut(|p_data| {
-                                assert!(p_data.len() == chunk.texels.len() * SURFACE_FORMAT_BPP);
-                                // FIXME: This doesn't care about bytes_per_pixel
-                                for xy in 0..chunk.texels.len() {
-                                    let i = xy * SURFACE_FORMAT_BPP;
-                                    let (r, g, b, a) = color_map[&chunk.texels[xy].id];
-                                    p_data[i + 0] = r;
-                                    p_data[i + 1] = g;
-                                    p_data[i + 2] = b;
-                                    p_data[i + 3] = a;
+                            let repaint = repaints
+                                .entry(index)
+                                .or_insert_with(|| Repaint::Texels(HashSet::new()));
+                            match repaint {
+                                Repaint::Texels(positions) => {
+                                    positions.extend(changes.iter().map(|change| change.position))
                                 }
-                            })
+                                Repaint::All => (),
+                            }
                         }
                         TerrainUpdate::None => (),
                     }
@@ -130,6 +145,40 @@ impl<'a> System<'a> for TerrainRender {
             None => (),
         };
 
+        // Chunks whose entity isn't created yet are kept for the next frame
+        let (repaints, deferred): (Vec<(Vector2I, Repaint)>, Vec<(Vector2I, Repaint)>) = repaints
+            .into_iter()
+            .filter(|(index, _)| terrain.is_chunk_loaded(index))
+            .partition(|(index, _)| {
+                (&chunk_index, &render_target)
+                    .join()
+                    .any(|(chunk_index, _)| chunk_index.index == *index)
+            });
+        self.deferred.extend(deferred);
+
+        // SDL surfaces stay on this thread, only the colours are computed in parallel
+        let colors: Vec<(Vector2I, Vec<(Vector2I, MaterialColor)>)> = {
+            let (terrain, materials): (&Terrain, &Materials) = (&terrain, &materials);
+            repaints
+                .par_iter()
+                .filter_map(|(index, repaint)| {
+                    terrain
+                        .index_to_chunk(index)
+                        .map(|chunk| (*index, Self::colors(chunk, index, materials, repaint)))
+                })
+                .collect()
+        };
+        for (index, colors) in colors {
+            let (_, render_target) = match (&chunk_index, &mut render_target)
+                .join()
+                .find(|(chunk_index, _)| chunk_index.index == index)
+            {
+                Some(value) => value,
+                None => continue,
+            };
+            Self::paint(render_target, &colors);
+        }
+
         self.terrain_listener = Some(terrain.get_listener());
     }
 }
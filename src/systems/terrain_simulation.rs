@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+
+use crate::{
+    mst::falling_sand,
+    resources::{Materials, Terrain, TerrainUpdate, Time},
+    util::{Listener, Vector2I},
+};
+use specs::{Read, System, Write};
+
+/// Moves powder and liquid texels, only in chunks where something changed recently
+pub struct TerrainSimulation {
+    terrain_listener: Option<Listener>,
+    active_chunks: HashSet<Vector2I>,
+    step_carry_over: f32,
+    tick: u64,
+}
+
+impl TerrainSimulation {
+    pub const TIME_STEP: f32 = 1.0 / 60.0;
+    const MAX_STEPS: u16 = 4;
+
+    pub fn new() -> TerrainSimulation {
+        TerrainSimulation {
+            terrain_listener: None,
+            active_chunks: HashSet::new(),
+            step_carry_over: 0.0,
+            tick: 0,
+        }
+    }
+
+    /// Mark the chunk and its neighbours, texels can move in from any side
+    fn activate(&mut self, index: &Vector2I) {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                self.active_chunks.insert(*index + Vector2I { x, y });
+            }
+        }
+    }
+}
+
+impl Default for TerrainSimulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> System<'a> for TerrainSimulation {
+    type SystemData = (Read<'a, Materials>, Read<'a, Time>, Write<'a, Terrain>);
+
+    fn run(&mut self, (materials, time, mut terrain): Self::SystemData) {
+        let events = match self.terrain_listener {
+            Some(listener) => terrain.consume_changes(listener),
+            None => {
+                // Initialize
+                self.active_chunks = falling_sand::active_chunks(&terrain, &materials);
+                None
+            }
+        };
+        match events {
+            Some(events) => {
+                for event in events {
+                    match event {
+                        TerrainUpdate::ChunkAdded(index)
+                        | TerrainUpdate::TexelsUpdated(index, _) => self.activate(&index),
+                        TerrainUpdate::ChunkRemoved(index) => {
+                            self.active_chunks.remove(&index);
+                        }
+                        TerrainUpdate::None => (),
+                    }
+                }
+            }
+            None => (),
+        };
+
+        // Fixed tick, independent of the frame rate
+        self.step_carry_over += time.delta_time.as_secs_f32();
+        let mut step_count = 0;
+        while self.step_carry_over >= Self::TIME_STEP && step_count < Self::MAX_STEPS {
+            let moved =
+                falling_sand::step(&mut terrain, &materials, &self.active_chunks, self.tick);
+            self.active_chunks.clear();
+            for index in moved {
+                self.activate(&index);
+            }
+            self.step_carry_over -= Self::TIME_STEP;
+            self.tick += 1;
+            step_count += 1;
+        }
+        // Drop the remaining time instead of trying to catch up later
+        if step_count >= Self::MAX_STEPS {
+            self.step_carry_over = 0.0;
+        }
+
+        self.terrain_listener = Some(terrain.get_listener());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{
+    gl::renderer::INIT_WINDOW_SIZE,
+    mst::utils::global_to_index,
+    resources::{Camera, Terrain},
+    util::{Vector2F, Vector2I},
+};
+use specs::{Read, System, Write};
+
+/// Loads chunks that come within `radius` chunks of the camera and unloads the ones that leave it.
+pub struct TerrainStreaming {
+    radius: i32,
+}
+
+impl TerrainStreaming {
+    /// Chunks are kept loaded until they are this many chunks outside the load radius,
+    /// so that moving back and forth over a chunk border doesn't reload the same chunks every frame.
+    const UNLOAD_MARGIN: i32 = 1;
+
+    pub fn new(radius: i32) -> TerrainStreaming {
+        TerrainStreaming { radius }
+    }
+
+    /// Global texel position at the center of the screen
+    fn camera_focus(camera: &Camera) -> Vector2F {
+        let screen_center = Vector2F {
+            x: INIT_WINDOW_SIZE.0 as f32,
+            y: INIT_WINDOW_SIZE.1 as f32,
+        } / 2.0;
+        (camera.transform.get_position() + screen_center) / camera.transform.get_scale()
+    }
+
+    fn is_within(offset: Vector2I, radius: i32) -> bool {
+        offset.x * offset.x + offset.y * offset.y <= radius * radius
+    }
+}
+
+impl<'a> System<'a> for TerrainStreaming {
+    type SystemData = (Read<'a, Camera>, Write<'a, Terrain>);
+
+    fn run(&mut self, (camera, mut terrain): Self::SystemData) {
+        let center = global_to_index(&Self::camera_focus(&camera).rounded());
+
+        for y in -self.radius..=self.radius {
+            for x in -self.radius..=self.radius {
+                let offset = Vector2I { x, y };
+                if Self::is_within(offset, self.radius) {
+                    terrain.load_chunk(center + offset);
+                }
+            }
+        }
+
+        let unload_radius = self.radius + Self::UNLOAD_MARGIN;
+        let far_chunks: Vec<Vector2I> = terrain
+            .chunk_iter()
+            .map(|(index, _)| *index)
+            .filter(|index| !Self::is_within(*index - center, unload_radius))
+            .collect();
+        for index in far_chunks {
+            terrain.unload_chunk(index);
+        }
+    }
+}
@@ -2,16 +2,13 @@ use std::collections::HashSet;
 
 use crate::{
     components::{ChunkIndex, PhysicsBody, RenderTarget, Transform},
-    mst::{chunk::Chunk, marching_square, utils::index_to_global},
+    mst::{chunk::Chunk, utils::index_to_global},
     resources::{Terrain, UnsafeBox2D},
-    util::{
-        box2d::{create_body, create_segmented_shape},
-        SortingOrder, Vector2F, Vector2I,
-    },
+    util::{box2d::create_body, SortingOrder, Vector2F, Vector2I},
 };
 
-use box2d_rs::{b2_body::B2bodyType, shapes::b2_chain_shape::B2chainShape};
-use specs::{Entities, Read, System, WriteStorage};
+use box2d_rs::b2_body::B2bodyType;
+use specs::{Entities, Entity, Join, Read, System, WriteStorage};
 
 pub struct TerrainSync {
     chunk_set: HashSet<Vector2I>,
@@ -48,60 +45,65 @@ impl<'a> System<'a> for TerrainSync {
             box2d,
         ): Self::SystemData,
     ) {
-        // Add new chunks
-        for (index, chunk) in terrain.chunk_iter() {
-            if !self.chunk_set.contains(index) {
-                let transform_component =
-                    Transform::new(Vector2F::from(index_to_global(index)), 0.0, Vector2F::ONE);
+        // Add new chunks, in a fixed order so that the physics bodies are created the same way every run
+        let mut added: Vec<&Vector2I> = terrain
+            .chunk_iter()
+            .map(|(index, _)| index)
+            .filter(|index| !self.chunk_set.contains(*index))
+            .collect();
+        added.sort_by_key(|index| (index.y, index.x));
+        for index in added {
+            let transform_component =
+                Transform::new(Vector2F::from(index_to_global(index)), 0.0, Vector2F::ONE);
 
-                let now = std::time::SystemTime::now();
-                let islands = marching_square::calculate_collisions(chunk);
-                println!(
-                    "{}: collision generation took {}ms {} shapes",
-                    index,
-                    now.elapsed().unwrap().as_millis(),
-                    islands.len()
-                );
-                let mut shapes: Vec<B2chainShape> = Vec::with_capacity(islands.len());
-                for island in islands {
-                    shapes.push(create_segmented_shape(island))
-                }
+            // Collision shapes are generated by TerrainCollision
+            let body = PhysicsBody::new(create_body(
+                box2d.world_ptr.clone(),
+                Some(B2bodyType::B2StaticBody),
+                vec![],
+                vec![],
+                Some(transform_component.get_position()),
+                Some(transform_component.get_rotation()),
+            ));
 
-                let body = PhysicsBody::new(create_body(
-                    box2d.world_ptr.clone(),
-                    Some(B2bodyType::B2StaticBody),
-                    vec![],
-                    shapes,
-                    Some(transform_component.get_position()),
-                    Some(transform_component.get_rotation()),
-                ));
-
-                entities
-                    .build_entity()
-                    .with(transform_component, &mut transform)
-                    .with(
-                        ChunkIndex {
-                            index: index.to_owned(),
-                        },
-                        &mut chunk_index,
-                    )
-                    .with(
-                        RenderTarget::new(
-                            Chunk::SIZE.x as u32,
-                            Chunk::SIZE.y as u32,
-                            Vector2F::ZERO,
-                            SortingOrder::Default as i16,
-                            false,
-                        ),
-                        &mut render_target,
-                    )
-                    .with(body, &mut physics_body)
-                    .build();
-                self.chunk_set.insert(index.to_owned());
-            }
+            entities
+                .build_entity()
+                .with(transform_component, &mut transform)
+                .with(
+                    ChunkIndex {
+                        index: index.to_owned(),
+                    },
+                    &mut chunk_index,
+                )
+                .with(
+                    RenderTarget::new(
+                        Chunk::SIZE.x as u32,
+                        Chunk::SIZE.y as u32,
+                        Vector2F::ZERO,
+                        SortingOrder::Default as i16,
+                        false,
+                    ),
+                    &mut render_target,
+                )
+                .with(body, &mut physics_body)
+                .build();
+            self.chunk_set.insert(index.to_owned());
         }
 
         // Remove deleted chunks
+        // The components are still available until the world is maintained,
+        // so TerrainRender and TerrainCollision can release the surface and body.
+        let removed: Vec<Entity> = (&entities, &chunk_index)
+            .join()
+            .filter(|(_, chunk)| terrain.index_to_chunk(&chunk.index).is_none())
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in removed {
+            match entities.delete(entity) {
+                Ok(_) => (),
+                Err(error) => println!("Failed to delete chunk entity: {error:?}"),
+            }
+        }
         self.chunk_set
             .retain(|index| terrain.index_to_chunk(index).is_some());
     }
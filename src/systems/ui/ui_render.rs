@@ -1,4 +1,4 @@
-use specs::{Join, ReadStorage, System, WriteStorage};
+use specs::{Join, System, WriteStorage};
 
 use crate::components::{ui::TextElement, RenderTarget};
 
@@ -13,21 +13,27 @@ impl UIRender {
 impl<'a> System<'a> for UIRender {
     type SystemData = (
         WriteStorage<'a, RenderTarget<'static>>,
-        ReadStorage<'a, TextElement>,
+        WriteStorage<'a, TextElement>,
     );
 
-    fn run(&mut self, (mut render_target, text): Self::SystemData) {
-        for (render_target, element) in (&mut render_target, &text).join() {
-            let font = element
-                .get_font()
-                .lock()
-                .expect("Failed to lock font mutex");
-            render_target.set_surface(
+    fn run(&mut self, (mut render_target, mut text): Self::SystemData) {
+        for (render_target, element) in (&mut render_target, &mut text).join() {
+            // Only render text that has changed, so its texture can stay cached
+            if !element.is_dirty() {
+                continue;
+            }
+            let surface = {
+                let font = element
+                    .get_font()
+                    .lock()
+                    .expect("Failed to lock font mutex");
                 match font.render(element.get_text()).solid(element.get_color()) {
                     Ok(surface) => surface,
                     Err(error) => panic!("Failed to render text: {error:?}"),
-                },
-            );
+                }
+            };
+            render_target.set_surface(surface);
+            element.clear_dirty();
         }
     }
 }
@@ -1,16 +1,24 @@
+mod args;
 pub mod box2d;
 mod change_buffer;
 pub mod font;
 pub mod math;
+pub mod noise;
+pub mod polygon;
 mod segment2_i32;
 mod sorting_order;
+#[cfg(test)]
+mod temp_path;
 mod vector2;
 mod vector2_f32;
 mod vector2_i32;
 
+pub use args::*;
 pub use change_buffer::*;
 pub use segment2_i32::*;
 pub use sorting_order::*;
+#[cfg(test)]
+pub use temp_path::*;
 pub use vector2::*;
 pub use vector2_f32::*;
 pub use vector2_i32::*;
@@ -0,0 +1,11 @@
+/// Value following the command line argument `name`, as in `--name <value>`
+pub fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+}
+
+/// Whether the command line arguments contain the flag `name`
+pub fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|arg| arg == name)
+}
@@ -2,7 +2,7 @@ use std::{cell::RefCell, rc::Rc};
 
 use box2d_rs::{
     b2_body::{B2body, B2bodyDef, B2bodyType},
-    b2_fixture::B2fixtureDef,
+    b2_fixture::{B2fixtureDef, FixturePtr},
     b2_math::B2vec2,
     b2_world::{B2world, B2worldPtr},
     shapes::{b2_chain_shape::B2chainShape, b2_polygon_shape::B2polygonShape},
@@ -14,7 +14,10 @@ use crate::{
     resources::{Box2D, UnsafeBody, UserData},
 };
 
-use super::{SortingOrder, Vector2F};
+use super::{polygon::decompose_convex, SortingOrder, Vector2F};
+
+/// Box2D's limit of vertices per polygon
+pub const MAX_POLYGON_VERTICES: usize = 8;
 
 pub fn b2vec_to_vector2f(value: B2vec2) -> Vector2F {
     Vector2F {
@@ -30,19 +33,20 @@ pub fn vector2f_to_b2vec(value: Vector2F) -> B2vec2 {
     }
 }
 
-/// <strong>Note: this function is not fully implemented</strong>
-///
-/// Only accepts max 8 vertices, and only produces convex shapes.
-pub fn create_solid_shape(points: Vec<Vector2F>) -> Vec<B2polygonShape> {
-    // TODO: handle more than 8 vertices and convex shapes
+/// Decompose a simple polygon, which may be concave, into convex polygons that Box2D accepts.
+/// `None` if the polygon isn't simple.
+pub fn create_solid_shape(points: Vec<Vector2F>) -> Option<Vec<B2polygonShape>> {
     let mut polygons: Vec<B2polygonShape> = Vec::new();
-    {
+    for piece in decompose_convex(&points, MAX_POLYGON_VERTICES)? {
+        if piece.len() < 3 {
+            continue;
+        }
         let mut shape = B2polygonShape::default();
-        let points: Vec<B2vec2> = points.iter().map(|p| vector2f_to_b2vec(*p)).collect();
+        let points: Vec<B2vec2> = piece.iter().map(|p| vector2f_to_b2vec(*p)).collect();
         shape.set(&points[..]);
         polygons.push(shape);
     }
-    polygons
+    Some(polygons)
 }
 
 pub fn create_segmented_shape(points: Vec<Vector2F>) -> B2chainShape {
@@ -52,6 +56,22 @@ pub fn create_segmented_shape(points: Vec<Vector2F>) -> B2chainShape {
     shape
 }
 
+/// Open chain that continues past both ends, the ghost vertices keep bodies from snagging on its end points
+pub fn create_chain_shape(
+    points: Vec<Vector2F>,
+    prev_vertex: Vector2F,
+    next_vertex: Vector2F,
+) -> B2chainShape {
+    let mut shape = B2chainShape::default();
+    let points: Vec<B2vec2> = points.iter().map(|p| vector2f_to_b2vec(*p)).collect();
+    shape.create_chain(
+        &points[..],
+        vector2f_to_b2vec(prev_vertex),
+        vector2f_to_b2vec(next_vertex),
+    );
+    shape
+}
+
 pub fn create_box(
     specs_world: &mut World,
     box2d_world: B2worldPtr<UserData>,
@@ -171,6 +191,10 @@ pub fn create_body(
     UnsafeBody::new(body_ptr)
 }
 
+pub fn destroy_body(world: B2worldPtr<UserData>, body_ptr: UnsafeBody) {
+    B2world::destroy_body(&mut world.borrow_mut(), body_ptr.i);
+}
+
 pub fn replace_shape(
     body_ptr: UnsafeBody,
     solid_shapes: Vec<B2polygonShape>,
@@ -233,6 +257,36 @@ pub fn replace_shape(
     }
 }
 
+/// Add a single chain fixture, for bodies whose fixtures are updated one by one.
+/// `friction` is ignored for kinematic bodies.
+pub fn add_chain_fixture(
+    body_ptr: &UnsafeBody,
+    shape: B2chainShape,
+    friction: f32,
+) -> FixturePtr<UserData> {
+    let mut fixture_def: B2fixtureDef<UserData> = B2fixtureDef::default();
+    fixture_def.shape = Some(Rc::new(RefCell::new(shape)));
+    match body_ptr.borrow().get_type() {
+        B2bodyType::B2StaticBody => {
+            fixture_def.density = 0.0;
+            fixture_def.friction = friction;
+        }
+        B2bodyType::B2KinematicBody => {
+            fixture_def.density = 1.0;
+            fixture_def.friction = 0.0;
+        }
+        B2bodyType::B2DynamicBody => {
+            fixture_def.density = 1.0;
+            fixture_def.friction = friction;
+        }
+    }
+    B2body::create_fixture(body_ptr.clone().i, &fixture_def)
+}
+
+pub fn remove_fixture(body_ptr: &UnsafeBody, fixture: FixturePtr<UserData>) {
+    B2body::destroy_fixture(body_ptr.clone().i, fixture);
+}
+
 #[cfg(test)]
 mod tests {
     use box2d_rs::b2_math::B2vec2;
@@ -0,0 +1,71 @@
+use super::math::lerp;
+
+/// Hash a lattice point into a pseudo-random 64 bit value.
+///
+/// Pure integer arithmetic, so the result only depends on the inputs (no platform or run differences).
+pub fn hash(seed: u64, x: i32, y: i32) -> u64 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    // splitmix64 finalizer
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+/// Random value in range [0, 1) for a lattice point
+fn lattice_value(seed: u64, x: i32, y: i32) -> f32 {
+    (hash(seed, x, y) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Smoothly interpolated value noise in range [0, 1)
+pub fn value_noise_2d(seed: u64, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+    let (x0, y0) = (x0 as i32, y0 as i32);
+
+    let top = lerp(
+        lattice_value(seed, x0, y0),
+        lattice_value(seed, x0 + 1, y0),
+        tx,
+    );
+    let bottom = lerp(
+        lattice_value(seed, x0, y0 + 1),
+        lattice_value(seed, x0 + 1, y0 + 1),
+        tx,
+    );
+    lerp(top, bottom, ty)
+}
+
+/// Value noise along a single axis in range [0, 1)
+pub fn value_noise_1d(seed: u64, x: f32) -> f32 {
+    value_noise_2d(seed, x, 0.0)
+}
+
+/// Layered value noise (octaves with halving amplitude and doubling frequency), normalized to range [0, 1)
+pub fn fractal_noise_2d(seed: u64, x: f32, y: f32, octaves: u8) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut total_amplitude = 0.0;
+    for octave in 0..octaves.max(1) {
+        sum += value_noise_2d(
+            seed.wrapping_add(octave as u64),
+            x * frequency,
+            y * frequency,
+        ) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total_amplitude
+}
+
+/// Layered value noise along a single axis, normalized to range [0, 1)
+pub fn fractal_noise_1d(seed: u64, x: f32, octaves: u8) -> f32 {
+    fractal_noise_2d(seed, x, 0.0, octaves)
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+
+use super::Vector2F;
+
+/// Cross product of `a - origin` and `b - origin`
+pub fn cross(origin: Vector2F, a: Vector2F, b: Vector2F) -> f32 {
+    (a.x - origin.x) * (b.y - origin.y) - (a.y - origin.y) * (b.x - origin.x)
+}
+
+/// Whether `point` lies on the segment, but not on either of its end points
+fn inside_segment(point: Vector2F, (a, b): (Vector2F, Vector2F)) -> bool {
+    let (ab, ap, bp) = (b - a, point - a, point - b);
+    cross(a, b, point) == 0.0 && ap.x * ab.x + ap.y * ab.y > 0.0 && bp.x * ab.x + bp.y * ab.y < 0.0
+}
+
+/// Whether the segments cross, or an end point of one lies inside the other.
+///
+/// Segments that only share end points, like neighbouring sides of an outline, don't cross.
+pub fn segments_cross(a: (Vector2F, Vector2F), b: (Vector2F, Vector2F)) -> bool {
+    let (d1, d2) = (cross(a.0, a.1, b.0), cross(a.0, a.1, b.1));
+    let (d3, d4) = (cross(b.0, b.1, a.0), cross(b.0, b.1, a.1));
+    (d1 * d2 < 0.0 && d3 * d4 < 0.0)
+        || inside_segment(b.0, a)
+        || inside_segment(b.1, a)
+        || inside_segment(a.0, b)
+        || inside_segment(a.1, b)
+}
+
+/// Positive for loops that turn towards positive rotation (clockwise on screen, where y points down)
+pub fn signed_area(points: &[Vector2F]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        area += a.x * b.y - b.x * a.y;
+    }
+    area / 2.0
+}
+
+/// Smallest and largest coordinates of the points
+pub fn bounds(points: &[Vector2F]) -> (Vector2F, Vector2F) {
+    let mut min = Vector2F {
+        x: f32::INFINITY,
+        y: f32::INFINITY,
+    };
+    let mut max = min * -1.0;
+    for point in points {
+        min = Vector2F {
+            x: min.x.min(point.x),
+            y: min.y.min(point.y),
+        };
+        max = Vector2F {
+            x: max.x.max(point.x),
+            y: max.y.max(point.y),
+        };
+    }
+    (min, max)
+}
+
+/// Remove repeated points and points that lie on a straight line between their neighbours
+pub fn remove_collinear(points: &[Vector2F]) -> Vec<Vector2F> {
+    let mut result: Vec<Vector2F> = points.to_vec();
+    result.dedup();
+    while result.len() > 1 && result.first() == result.last() {
+        result.pop();
+    }
+    let mut i = 0;
+    while result.len() >= 3 && i < result.len() {
+        let len = result.len();
+        let (prev, next) = (result[(i + len - 1) % len], result[(i + 1) % len]);
+        if cross(prev, result[i], next).abs() <= f32::EPSILON {
+            result.remove(i);
+            // The previous point might have become collinear
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+    result
+}
+
+fn contains_point(triangle: [Vector2F; 3], point: Vector2F) -> bool {
+    let [a, b, c] = triangle;
+    cross(a, b, point) >= 0.0 && cross(b, c, point) >= 0.0 && cross(c, a, point) >= 0.0
+}
+
+/// Whether no two sides of the polygon cross, see `segments_cross`
+pub fn is_simple(points: &[Vector2F]) -> bool {
+    let len = points.len();
+    let side = |i: usize| (points[i], points[(i + 1) % len]);
+    let x_range = |i: usize| {
+        let (a, b) = side(i);
+        (a.x.min(b.x), a.x.max(b.x))
+    };
+    // Sweep the sides from left to right, only sides that overlap along x can cross
+    let mut sides: Vec<usize> = (0..len).collect();
+    sides.sort_by(|a, b| x_range(*a).0.total_cmp(&x_range(*b).0));
+    (0..len).all(|k| {
+        let max_x = x_range(sides[k]).1;
+        sides[k + 1..]
+            .iter()
+            .take_while(|other| x_range(**other).0 <= max_x)
+            .all(|other| !segments_cross(side(sides[k]), side(*other)))
+    })
+}
+
+/// Ear clipping triangulation of a simple polygon.
+///
+/// Returns triangles as indices to `points`, wound the same way as a polygon with a positive signed area,
+/// or `None` if the polygon isn't simple.
+pub fn triangulate(points: &[Vector2F]) -> Option<Vec<[usize; 3]>> {
+    if !is_simple(points) {
+        return None;
+    }
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    if signed_area(points) < 0.0 {
+        remaining.reverse();
+    }
+    let turn = |remaining: &[usize], i: usize| {
+        let len = remaining.len();
+        cross(
+            points[remaining[(i + len - 1) % len]],
+            points[remaining[i]],
+            points[remaining[(i + 1) % len]],
+        )
+    };
+    // Only reflex vertices can be inside an ear, and clipping ears never makes a vertex reflex
+    let mut reflex: Vec<usize> = (0..remaining.len())
+        .filter(|&i| turn(&remaining, i) <= 0.0)
+        .map(|i| remaining[i])
+        .collect();
+
+    let mut triangles: Vec<[usize; 3]> = Vec::with_capacity(points.len().saturating_sub(2));
+    // The next ear is usually next to the last one, so the search continues from there
+    let mut start = 0;
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let ear = (0..len).map(|k| (start + k) % len).find(|&i| {
+            if turn(&remaining, i) <= 0.0 {
+                return false;
+            }
+            let (prev, current, next) = (
+                remaining[(i + len - 1) % len],
+                remaining[i],
+                remaining[(i + 1) % len],
+            );
+            let triangle = [points[prev], points[current], points[next]];
+            // No other vertex may be inside the ear
+            reflex.iter().all(|&other| {
+                other == prev
+                    || other == current
+                    || other == next
+                    || triangle.contains(&points[other])
+                    || !contains_point(triangle, points[other])
+            })
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + len - 1) % len],
+                    remaining[i],
+                    remaining[(i + 1) % len],
+                ]);
+                remaining.remove(i);
+                start = i.saturating_sub(1);
+                // The neighbours of the ear might have become convex
+                let len = remaining.len();
+                let neighbours = [(i + len - 1) % len, i % len];
+                for neighbour in neighbours {
+                    if turn(&remaining, neighbour) > 0.0 {
+                        let id = remaining[neighbour];
+                        reflex.retain(|other| *other != id);
+                    }
+                }
+            }
+            None => return None,
+        }
+    }
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+    Some(triangles)
+}
+
+fn is_convex(points: &[Vector2F], piece: &[usize]) -> bool {
+    let len = piece.len();
+    (0..len).all(|i| {
+        cross(
+            points[piece[(i + len - 1) % len]],
+            points[piece[i]],
+            points[piece[(i + 1) % len]],
+        ) >= 0.0
+    })
+}
+
+/// Merge two pieces along the edge `from` to `to` of `a`, which `b` goes along in the opposite direction
+fn merge_pieces(a: &[usize], b: &[usize], (from, to): (usize, usize)) -> Vec<usize> {
+    let i = a.iter().position(|&v| v == from).unwrap();
+    let j = b.iter().position(|&v| v == to).unwrap();
+    let mut merged: Vec<usize> = Vec::with_capacity(a.len() + b.len() - 2);
+    // Around `a` from `to` to `from`, then around `b` strictly between `from` and `to`
+    for k in 0..a.len() {
+        merged.push(a[(i + 1 + k) % a.len()]);
+    }
+    for k in 2..b.len() {
+        merged.push(b[(j + k) % b.len()]);
+    }
+    merged
+}
+
+/// Piece that the given triangle has been merged into
+fn find_piece(merged_into: &mut [usize], triangle: usize) -> usize {
+    let mut piece = triangle;
+    while merged_into[piece] != piece {
+        piece = merged_into[piece];
+    }
+    merged_into[triangle] = piece;
+    piece
+}
+
+/// Decompose a simple polygon into convex pieces of at most `max_vertices` vertices.
+///
+/// Triangulates the polygon and then removes each diagonal once if the pieces on both sides stay convex
+/// (Hertel-Mehlhorn). `None` if the polygon can't be triangulated, see `triangulate`.
+pub fn decompose_convex(points: &[Vector2F], max_vertices: usize) -> Option<Vec<Vec<Vector2F>>> {
+    let points = remove_collinear(points);
+    let triangles = triangulate(&points)?;
+    let mut pieces: Vec<Option<Vec<usize>>> = triangles
+        .iter()
+        .map(|triangle| Some(triangle.to_vec()))
+        .collect();
+    // Each triangle starts as its own piece
+    let mut merged_into: Vec<usize> = (0..triangles.len()).collect();
+
+    // Every diagonal is an edge of two triangles, going opposite ways
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            edges.insert((triangle[k], triangle[(k + 1) % 3]), t);
+        }
+    }
+    for (t, triangle) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            let (from, to) = (triangle[k], triangle[(k + 1) % 3]);
+            let other = match edges.get(&(to, from)) {
+                // Visit each diagonal from one side only
+                Some(other) if from < to => *other,
+                _ => continue,
+            };
+            let (a, b) = (
+                find_piece(&mut merged_into, t),
+                find_piece(&mut merged_into, other),
+            );
+            let piece = merge_pieces(
+                pieces[a].as_ref().unwrap(),
+                pieces[b].as_ref().unwrap(),
+                (from, to),
+            );
+            if piece.len() <= max_vertices && is_convex(&points, &piece) {
+                pieces[a] = Some(piece);
+                pieces[b] = None;
+                merged_into[b] = a;
+            }
+        }
+    }
+
+    Some(
+        pieces
+            .iter()
+            .flatten()
+            .map(|piece| remove_collinear(&piece.iter().map(|i| points[*i]).collect::<Vec<_>>()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cross, decompose_convex, is_simple, signed_area, triangulate};
+    use crate::util::Vector2F;
+
+    #[test]
+    fn concave() {
+        // U shape
+        let points = [
+            Vector2F { x: 0.0, y: 0.0 },
+            Vector2F { x: 1.0, y: 0.0 },
+            Vector2F { x: 1.0, y: 2.0 },
+            Vector2F { x: 2.0, y: 2.0 },
+            Vector2F { x: 2.0, y: 0.0 },
+            Vector2F { x: 3.0, y: 0.0 },
+            Vector2F { x: 3.0, y: 3.0 },
+            Vector2F { x: 0.0, y: 3.0 },
+        ];
+        let area = signed_area(&points).abs();
+        assert_eq!(triangulate(&points).unwrap().len(), points.len() - 2);
+
+        let pieces = decompose_convex(&points, 8).unwrap();
+        assert!(pieces.len() >= 2);
+        let mut total_area = 0.0;
+        for piece in pieces.iter() {
+            assert!(piece.len() <= 8);
+            let len = piece.len();
+            for i in 0..len {
+                let turn = cross(piece[(i + len - 1) % len], piece[i], piece[(i + 1) % len]);
+                assert!(turn * signed_area(piece) > 0.0);
+            }
+            total_area += signed_area(piece).abs();
+        }
+        assert!((total_area - area).abs() < 0.001);
+
+        // Collinear points along the edges of a square
+        let square = [
+            Vector2F { x: 0.0, y: 0.0 },
+            Vector2F { x: 1.0, y: 0.0 },
+            Vector2F { x: 2.0, y: 0.0 },
+            Vector2F { x: 2.0, y: 2.0 },
+            Vector2F { x: 0.0, y: 2.0 },
+            Vector2F { x: 0.0, y: 1.0 },
+        ];
+        let pieces = decompose_convex(&square, 8).unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(pieces[0].len(), 4);
+        assert_eq!(signed_area(&pieces[0]).abs(), 4.0);
+    }
+
+    #[test]
+    fn not_simple() {
+        // Pentagram, every side crosses two others
+        let points: Vec<Vector2F> = (0..5)
+            .map(|i| {
+                let angle = (i * 2) as f32 * std::f32::consts::TAU / 5.0;
+                Vector2F {
+                    x: angle.cos(),
+                    y: angle.sin(),
+                }
+            })
+            .collect();
+        assert!(!is_simple(&points));
+        assert!(triangulate(&points).is_none());
+        assert!(decompose_convex(&points, 8).is_none());
+    }
+
+    #[test]
+    fn jagged() {
+        // Saw blade with teeth along the top, in screen space where y points down
+        let teeth = 500;
+        let mut points: Vec<Vector2F> = vec![
+            Vector2F { x: 0.0, y: 10.0 },
+            Vector2F {
+                x: teeth as f32 * 2.0,
+                y: 10.0,
+            },
+        ];
+        for i in (0..teeth).rev() {
+            let x = i as f32 * 2.0;
+            points.push(Vector2F { x: x + 2.0, y: 2.0 });
+            points.push(Vector2F { x: x + 1.0, y: 0.0 });
+        }
+        points.push(Vector2F { x: 0.0, y: 2.0 });
+        assert!(is_simple(&points));
+
+        let pieces = decompose_convex(&points, 8).unwrap();
+        let total_area: f32 = pieces.iter().map(|piece| signed_area(piece).abs()).sum();
+        assert!((total_area - signed_area(&points).abs()).abs() < 0.01);
+        // Each tooth needs a piece of its own, and the base is split where the teeth meet it
+        assert!(pieces.len() >= teeth);
+        assert!(pieces.len() <= teeth * 2);
+        for piece in pieces.iter() {
+            assert!(piece.len() <= 8);
+            let len = piece.len();
+            for i in 0..len {
+                let turn = cross(piece[(i + len - 1) % len], piece[i], piece[(i + 1) % len]);
+                assert!(turn * signed_area(piece) > 0.0);
+            }
+        }
+    }
+}
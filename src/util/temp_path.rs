@@ -0,0 +1,35 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Path of a file in the temp directory for tests, unique to the process and call.
+/// The file is removed when the path is dropped, also when a test fails.
+pub struct TempPath {
+    path: PathBuf,
+}
+
+impl TempPath {
+    pub fn new(name: &str) -> TempPath {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        TempPath {
+            path: std::env::temp_dir().join(format!("{}-{id}-{name}", std::process::id())),
+        }
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // The test might have failed before the file was written
+        let _ = fs::remove_file(&self.path);
+    }
+}